@@ -0,0 +1,79 @@
+//! Hashes the sources into an identifier of this build, so peers only play together when they run
+//! exactly the same code, even when it's built from an uncommitted tree or outside of git. Paths
+//! and line endings are normalised, so the same sources give the same identifier on every platform.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// Files outside `src` that change what gets built. Cargo.lock isn't one of them, because it's not
+/// committed, so it differs between machines.
+const EXTRA_INPUTS: [&str; 1] = ["Cargo.toml"];
+
+/// Collects the files under the given directory, in whatever order the platform lists them
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+fn main() {
+    let root = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+
+    let mut files = Vec::new();
+    collect_files(&root.join("src"), &mut files);
+    files.extend(EXTRA_INPUTS.iter().map(|name| root.join(name)));
+
+    // the same relative path on every platform, also to sort them by
+    let mut files: Vec<(String, PathBuf)> = files
+        .into_iter()
+        .map(|path| {
+            let relative = path
+                .strip_prefix(&root)
+                .unwrap_or(&path)
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            (relative, path)
+        })
+        .collect();
+    files.sort();
+
+    // FNV-1a, like the handshake hashes, because it's stable across platforms and compilers
+    let mut hash: u64 = 0xcbf29ce484222325;
+
+    for (relative, path) in &files {
+        let contents = fs::read(path).expect("failed to read a source file");
+
+        // a checkout with CRLF line endings has the same sources
+        let contents = contents.into_iter().filter(|byte| *byte != b'\r');
+
+        for byte in relative.bytes().chain(contents) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    println!("cargo:rerun-if-changed=src");
+
+    for name in EXTRA_INPUTS {
+        println!("cargo:rerun-if-changed={name}");
+    }
+
+    println!(
+        "cargo:rustc-env=BUILD_ID={}+{hash:016x}",
+        env::var("CARGO_PKG_VERSION").unwrap()
+    );
+}
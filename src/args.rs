@@ -1,6 +1,10 @@
 use bevy::prelude::*;
 use clap::Parser;
 
+/// characters used in room codes, leaving out the ones that are easily confused (0/O, 1/I)
+pub const ROOM_CODE_ALPHABET: &str = "ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+pub const ROOM_CODE_LENGTH: usize = 5;
+
 #[derive(Parser, Debug, Resource)]
 pub struct Args {
     /// runs the game in synctest mode
@@ -11,9 +15,21 @@ pub struct Args {
     #[clap(long)]
     pub local: bool,
 
-    /// input delay in frames
-    #[clap(long, default_value_t = 2)]
-    pub input_delay: usize,
+    /// runs the game in local mode against a bot
+    #[clap(long)]
+    pub bot: bool,
+
+    /// input delay in frames, measured from the connection to the other player when not given
+    #[clap(long)]
+    pub input_delay: Option<usize>,
+
+    /// lets bullets hit the tank that fired them, once they've flown for a while
+    #[clap(long)]
+    pub self_hits: bool,
+
+    /// gives both players a point when they destroy each other at the same time, instead of none
+    #[clap(long)]
+    pub draw_points: bool,
 
     /// enables debug mode
     #[clap(long)]
@@ -22,4 +38,70 @@ pub struct Args {
     /// matchbox URL
     #[clap(long, default_value_t = String::from("wss://match.remcokranenburg.com"))]
     pub match_url: String,
+
+    /// creates a private room with a random code
+    #[clap(long, conflicts_with = "room")]
+    pub private: bool,
+
+    /// joins the private room with the given code
+    #[clap(long, value_parser = parse_room_code, conflicts_with = "spectate")]
+    pub room: Option<String>,
+
+    /// runs without a window, playing a quick match with scripted inputs
+    #[clap(long)]
+    pub headless: bool,
+
+    /// frame at which a headless game prints its state and exits
+    #[clap(long, default_value_t = 600)]
+    pub frames: i32,
+
+    /// watches the match in the private room with the given code
+    #[clap(long, value_parser = parse_room_code, conflicts_with = "private")]
+    pub spectate: Option<String>,
+}
+
+impl Args {
+    /// Parses the arguments from the command line.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_env() -> Self {
+        Args::parse()
+    }
+
+    /// Parses the arguments from the URL query, where `?room=ABCDE&debug` is treated like
+    /// `--room=ABCDE --debug`.
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_env() -> Self {
+        let search = web_sys::window()
+            .and_then(|window| window.location().search().ok())
+            .unwrap_or_default();
+
+        let query_args = search
+            .trim_start_matches('?')
+            .split('&')
+            .filter(|param| !param.is_empty())
+            .map(|param| match param.split_once('=') {
+                Some((key, value)) if !value.is_empty() => format!("--{key}={value}"),
+                Some((key, _)) => format!("--{key}"),
+                None => format!("--{param}"),
+            });
+
+        let argv = std::iter::once(String::from("tunneltanktournament")).chain(query_args);
+
+        Args::try_parse_from(argv).unwrap_or_else(|e| {
+            warn!("Ignoring invalid URL query: {e}");
+            Args::parse_from(["tunneltanktournament"])
+        })
+    }
+}
+
+fn parse_room_code(code: &str) -> Result<String, String> {
+    let code = code.trim().to_uppercase();
+
+    if code.len() != ROOM_CODE_LENGTH || !code.chars().all(|c| ROOM_CODE_ALPHABET.contains(c)) {
+        return Err(format!(
+            "room codes are {ROOM_CODE_LENGTH} characters from {ROOM_CODE_ALPHABET}"
+        ));
+    }
+
+    Ok(code)
 }
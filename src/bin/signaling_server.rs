@@ -0,0 +1,267 @@
+//! Signaling server for Tunnel Tank Tournament, for playing without the public server.
+//!
+//! Peers connect to `ws://<host>/<room>`. Without a query, everyone in a room is connected to
+//! each other. With `?next=N`, the room hands out groups of N peers: each group is connected to
+//! each other, and the next peer starts a new group.
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    server::main();
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    panic!("the signaling server doesn't run in the browser");
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod server {
+    use async_trait::async_trait;
+    use axum::extract::ws::Message;
+    use clap::Parser;
+    use futures::StreamExt;
+    use matchbox_protocol::{JsonPeerEvent, PeerId, PeerRequest};
+    use matchbox_signaling::{
+        ClientRequestError, NoCallbacks, SignalingServerBuilder, SignalingState, SignalingTopology,
+        WsStateMeta,
+        common_logic::{SignalingChannel, StateObj, parse_request, try_send},
+    };
+    use std::{
+        collections::HashMap,
+        net::SocketAddr,
+        time::{Duration, Instant},
+    };
+    use tracing::{info, warn};
+    use tracing_subscriber::EnvFilter;
+
+    /// How long a connection may take to get from asking for a room to joining it, after which it's
+    /// assumed to have dropped
+    const JOIN_TIMEOUT: Duration = Duration::from_secs(60);
+
+    #[derive(Parser, Debug)]
+    struct Args {
+        /// address to listen on
+        #[clap(long, default_value = "0.0.0.0:3536")]
+        host: SocketAddr,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct RequestedRoom {
+        id: String,
+        /// The size of the groups to hand out, or `None` to connect everyone
+        next: Option<usize>,
+    }
+
+    /// A room that was asked for, but not joined yet
+    #[derive(Debug)]
+    struct PendingRoom {
+        room: RequestedRoom,
+        since: Instant,
+    }
+
+    impl PendingRoom {
+        fn new(room: RequestedRoom) -> Self {
+            PendingRoom {
+                room,
+                since: Instant::now(),
+            }
+        }
+
+        fn is_stale(&self) -> bool {
+            self.since.elapsed() > JOIN_TIMEOUT
+        }
+    }
+
+    #[derive(Default, Debug)]
+    struct Rooms {
+        /// Connections that asked for a room, but haven't been assigned an id yet
+        requested: HashMap<SocketAddr, PendingRoom>,
+        /// Peers that have an id, but haven't been introduced to anyone yet
+        assigned: HashMap<PeerId, PendingRoom>,
+        senders: HashMap<PeerId, SignalingChannel>,
+        /// Peers in each room that new peers are introduced to
+        open: HashMap<RequestedRoom, Vec<PeerId>>,
+        /// Complete groups handed out by `?next=N` rooms
+        matched: Vec<Vec<PeerId>>,
+    }
+
+    impl Rooms {
+        /// Adds the peer to its room, returning the peers it should be introduced to
+        fn join(&mut self, peer: PeerId, sender: SignalingChannel) -> Vec<PeerId> {
+            let Some(PendingRoom { room, .. }) = self.assigned.remove(&peer) else {
+                warn!("{peer} joined without asking for a room");
+                return Vec::new();
+            };
+
+            self.senders.insert(peer, sender);
+
+            let open = self.open.entry(room.clone()).or_default();
+            let others = open.clone();
+            open.push(peer);
+
+            if room.next == Some(open.len()) {
+                info!("Room {:?} handed out a group of {}", room.id, open.len());
+                self.matched.push(std::mem::take(open));
+            }
+
+            others
+        }
+
+        /// Removes the peer, returning the peers that should be told it left
+        fn leave(&mut self, peer: PeerId) -> Vec<PeerId> {
+            self.senders.remove(&peer);
+            self.assigned.remove(&peer);
+
+            let groups = self.matched.iter_mut().chain(self.open.values_mut());
+            let mut others = Vec::new();
+
+            for group in groups {
+                if group.contains(&peer) {
+                    group.retain(|p| *p != peer);
+                    others.clone_from(group);
+                    break;
+                }
+            }
+
+            self.matched.retain(|group| !group.is_empty());
+            self.open.retain(|_, group| !group.is_empty());
+
+            others
+        }
+
+        /// Forgets the rooms asked for by connections that dropped before they could join, which never
+        /// get to leave
+        fn forget_stale(&mut self) {
+            self.requested.retain(|_, pending| !pending.is_stale());
+            self.assigned.retain(|_, pending| !pending.is_stale());
+        }
+
+        fn send(&self, peer: PeerId, event: JsonPeerEvent) {
+            let Some(sender) = self.senders.get(&peer) else {
+                warn!("Can't send to unknown peer {peer}");
+                return;
+            };
+
+            if let Err(e) = try_send(sender, Message::Text(event.to_string().into())) {
+                warn!("Failed to send to {peer}: {e:?}");
+            }
+        }
+    }
+
+    #[derive(Default, Debug, Clone)]
+    struct ServerState(StateObj<Rooms>);
+
+    impl SignalingState for ServerState {}
+
+    #[derive(Debug, Default)]
+    struct NextTopology;
+
+    #[async_trait]
+    impl SignalingTopology<NoCallbacks, ServerState> for NextTopology {
+        async fn state_machine(upgrade: WsStateMeta<NoCallbacks, ServerState>) {
+            let WsStateMeta {
+                peer_id,
+                sender,
+                mut receiver,
+                state,
+                ..
+            } = upgrade;
+
+            {
+                let mut rooms = state.0.lock().unwrap();
+
+                for other in rooms.join(peer_id, sender) {
+                    rooms.send(other, JsonPeerEvent::NewPeer(peer_id));
+                }
+            }
+
+            while let Some(request) = receiver.next().await {
+                let request = match parse_request(request) {
+                    Ok(request) => request,
+                    Err(ClientRequestError::Axum(e)) => {
+                        warn!("Connection to {peer_id} failed: {e:?}");
+                        break;
+                    }
+                    Err(ClientRequestError::Close) => break,
+                    Err(e) => {
+                        warn!("Ignoring invalid request from {peer_id}: {e:?}");
+                        continue;
+                    }
+                };
+
+                match request {
+                    PeerRequest::Signal { receiver, data } => {
+                        let event = JsonPeerEvent::Signal {
+                            sender: peer_id,
+                            data,
+                        };
+                        state.0.lock().unwrap().send(receiver, event);
+                    }
+                    PeerRequest::KeepAlive => {} // only keeps the connection open
+                }
+            }
+
+            info!("Peer {peer_id} left");
+
+            let mut rooms = state.0.lock().unwrap();
+
+            for other in rooms.leave(peer_id) {
+                rooms.send(other, JsonPeerEvent::PeerLeft(peer_id));
+            }
+        }
+    }
+
+    // the connection request callback has to return an HTTP response as its error
+    #[allow(clippy::result_large_err)]
+    #[tokio::main]
+    pub async fn main() {
+        tracing_subscriber::fmt()
+            .with_env_filter(
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+            )
+            .init();
+
+        let args = Args::parse();
+        let state = ServerState::default();
+
+        info!("Signaling server listening on {}", args.host);
+
+        let server = SignalingServerBuilder::new(args.host, NextTopology, state.clone())
+            .on_connection_request({
+                let state = state.clone();
+                move |connection| {
+                    let room = RequestedRoom {
+                        id: connection.path.unwrap_or_default(),
+                        next: connection
+                            .query_params
+                            .get("next")
+                            .and_then(|next| next.parse().ok())
+                            .filter(|next| *next > 0),
+                    };
+                    let mut rooms = state.0.lock().unwrap();
+                    rooms.forget_stale();
+                    rooms
+                        .requested
+                        .insert(connection.origin, PendingRoom::new(room));
+                    Ok(true)
+                }
+            })
+            .on_id_assignment(move |(origin, peer_id)| {
+                let mut rooms = state.0.lock().unwrap();
+                match rooms.requested.remove(&origin) {
+                    Some(pending) => {
+                        info!("Peer {peer_id} joined room {:?}", pending.room.id);
+                        rooms.assigned.insert(peer_id, pending);
+                    }
+                    None => warn!("Peer {peer_id} was assigned an id without asking for a room"),
+                }
+            })
+            .cors()
+            .build();
+
+        server
+            .serve()
+            .await
+            .expect("failed to run the signaling server, is it already running?");
+    }
+}
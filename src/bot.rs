@@ -0,0 +1,41 @@
+use crate::input::{INPUT_DOWN, INPUT_FIRE, INPUT_LEFT, INPUT_RIGHT, INPUT_UP};
+use bevy::prelude::*;
+
+/// The bot always plays as the second player
+pub const BOT_HANDLE: usize = 1;
+
+const BOT_FIRE_RANGE: f32 = 60.0;
+const BOT_AIM_TOLERANCE: f32 = 1.5;
+
+/// Steers towards the target and fires when it lines up with one of the eight directions a tank
+/// can face
+pub fn bot_input(position: Vec2, target: Option<Vec2>) -> u8 {
+    let Some(target) = target else {
+        return 0; // nothing to hunt
+    };
+
+    let delta = target - position;
+    let mut input = 0u8;
+
+    if delta.x > BOT_AIM_TOLERANCE {
+        input |= INPUT_RIGHT;
+    } else if delta.x < -BOT_AIM_TOLERANCE {
+        input |= INPUT_LEFT;
+    }
+
+    if delta.y > BOT_AIM_TOLERANCE {
+        input |= INPUT_UP;
+    } else if delta.y < -BOT_AIM_TOLERANCE {
+        input |= INPUT_DOWN;
+    }
+
+    let aligned = delta.x.abs() < BOT_AIM_TOLERANCE
+        || delta.y.abs() < BOT_AIM_TOLERANCE
+        || (delta.x.abs() - delta.y.abs()).abs() < BOT_AIM_TOLERANCE;
+
+    if aligned && delta.length() < BOT_FIRE_RANGE {
+        input |= INPUT_FIRE;
+    }
+
+    input
+}
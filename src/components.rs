@@ -1,12 +1,28 @@
+use crate::{power_ups::PowerUpKind, weapons::WeaponKind};
 use bevy::prelude::*;
 use std::hash::{Hash, Hasher};
 
 #[derive(Component)]
 pub struct OnLoadingScreen;
 
+#[derive(Component)]
+pub struct OnMainMenuScreen;
+
 #[derive(Component)]
 pub struct OnMatchmakingScreen;
 
+#[derive(Component)]
+pub struct OnGameScreen;
+
+#[derive(Component)]
+pub struct OnConnectionOverlay;
+
+#[derive(Component)]
+pub struct OnNetworkStatsOverlay;
+
+#[derive(Component)]
+pub struct OverviewUi;
+
 #[derive(Component, Clone, Copy)]
 pub struct PlayerRef {
     pub id: usize,
@@ -25,14 +41,116 @@ pub struct CameraPosition {
 #[derive(Component, Clone, Copy)]
 pub struct Bullet {
     pub owner_id: usize,
+    pub weapon: WeaponKind,
+    /// How many more solid tiles this bullet bores through
+    pub penetration: u32,
+    /// Frames since the bullet was fired
+    pub age: u32,
 }
 
+/// Frames until the player can fire again
+#[derive(Component, Clone, Copy, Default)]
+pub struct FireCooldown(pub u32);
+
+/// Frames until the player can lay another mine
+#[derive(Component, Clone, Copy, Default)]
+pub struct MineCooldown(pub u32);
+
+/// Lies in a tunnel until a tank drives into it
+#[derive(Component, Clone, Copy)]
+pub struct Mine {
+    pub owner_id: usize,
+    /// Frames since the mine was laid
+    pub age: u32,
+}
+
+/// Marks a tank that was destroyed this frame, until the round ends at the end of the frame, so
+/// nothing destroys it twice. It never outlives the frame, so it isn't rolled back.
+#[derive(Component)]
+pub struct Destroyed;
+
+/// The weapon a player fires
+#[derive(Component, Clone, Copy, Default)]
+pub struct Weapon {
+    pub kind: WeaponKind,
+    /// Whether the switch key was released since the last switch
+    pub switch_ready: bool,
+}
+
+/// Buried in the terrain until a tank drives over it
 #[derive(Component, Clone, Copy)]
-pub struct BulletReady(pub bool);
+pub struct PowerUp {
+    pub kind: PowerUpKind,
+}
+
+/// Fills up a bar in the HUD to show how much a player has of something
+#[derive(Component, Clone, Copy)]
+pub struct Gauge {
+    pub player_id: usize,
+    pub kind: GaugeKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GaugeKind {
+    Energy,
+    Shield,
+    /// How far the weapon is from being ready to fire again
+    Reload,
+}
+
+/// Shows how long the current round has been going
+#[derive(Component)]
+pub struct RoundClockLabel;
+
+/// Draws a player's surroundings into a small image, at one pixel for every few tiles
+#[derive(Component)]
+pub struct Radar {
+    pub player_id: usize,
+    /// Pixels, column by column, that the player's tank has passed through
+    pub explored: Vec<bool>,
+}
+
+/// Shows the countdown before a round and the outcome after it
+#[derive(Component)]
+pub struct RoundBanner;
+
+/// Shows the weapon of a player in their viewport
+#[derive(Component, Clone, Copy)]
+pub struct WeaponLabel {
+    pub player_id: usize,
+}
 
 #[derive(Component, Clone, Copy)]
 pub struct MoveDir(pub Vec2);
 
+/// Where a tank or bullet is drawn, which moves smoothly in between the frames of the simulation.
+/// It only affects what is drawn, so it isn't rolled back.
+#[derive(Component, Clone, Copy)]
+pub struct Smoothing {
+    /// The simulated transform one frame before the current one
+    pub previous: Transform,
+    /// The simulated transform of the latest frame
+    pub current: Transform,
+    /// How far the drawing is off from the simulation after a rollback, which fades out
+    pub correction: Vec3,
+    /// Where it would have been drawn last time, had the rollback's corrections been known then
+    pub resimulated: Option<Vec3>,
+    /// The transform it is drawn with
+    pub visual: Transform,
+}
+
+impl Smoothing {
+    pub fn new(transform: Transform) -> Self {
+        Smoothing {
+            previous: transform,
+            current: transform,
+            correction: Vec3::ZERO,
+            resimulated: None,
+            visual: transform,
+        }
+    }
+}
+
 pub fn checksum_transform(transform: &Transform) -> u64 {
     let mut hasher = bevy_ggrs::checksum_hasher();
     assert!(
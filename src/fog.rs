@@ -0,0 +1,112 @@
+use crate::{
+    GameMode, MAX_NUM_PLAYERS, TERRAIN_HEIGHT, TERRAIN_WIDTH, Terrain, TerrainType,
+    args::Args,
+    components::{Bullet, CameraPosition, Player},
+    mines::mine_layer,
+};
+use bevy::{camera::visibility::RenderLayers, prelude::*};
+use bevy_ecs_tilemap::prelude::*;
+
+/// Distance at which a tank senses the enemy, even through the dirt
+const SENSE_RADIUS: f32 = 8.0;
+/// Distance a tank can see down a tunnel, which is about as far as its camera shows
+const SIGHT_RANGE: f32 = 40.0;
+/// Distance between the points checked along a line of sight, so no tile is skipped
+const SIGHT_STEP: f32 = 0.5;
+
+/// The render layer that shows what the given player can see of the enemy, which only their own
+/// follow camera looks at
+pub fn sight_layer(viewer_id: usize) -> usize {
+    1 + MAX_NUM_PLAYERS + viewer_id
+}
+
+/// Whether a tank at `from` can see something at `to`, when it's close or down an open tunnel
+fn can_see(terrain: &Terrain, from: Vec2, to: Vec2) -> bool {
+    let distance = from.distance(to);
+
+    if distance < SENSE_RADIUS {
+        return true;
+    }
+
+    if distance > SIGHT_RANGE {
+        return false;
+    }
+
+    let steps = (distance / SIGHT_STEP).ceil() as u32;
+
+    (0..=steps).all(|step| {
+        let point = from.lerp(to, step as f32 / steps as f32);
+        let pos = TilePos {
+            x: (point.x + TERRAIN_WIDTH as f32 / 2.0).floor() as u32,
+            y: (point.y + TERRAIN_HEIGHT as f32 / 2.0).floor() as u32,
+        };
+
+        terrain.get(&pos) == Some(TerrainType::Empty)
+    })
+}
+
+/// Shows tanks and bullets to their owner, and to every other player that can see them. This only
+/// changes what is drawn, so it runs outside the simulation.
+#[allow(clippy::type_complexity)]
+pub fn reveal_in_sight(
+    mut commands: Commands,
+    terrain: Res<Terrain>,
+    viewers: Query<(&Player, &Transform)>,
+    targets: Query<
+        (Entity, &Transform, Option<&Player>, Option<&Bullet>),
+        Or<(With<Player>, With<Bullet>)>,
+    >,
+) {
+    for (entity, transform, player, bullet) in &targets {
+        let owner_id = match (player, bullet) {
+            (Some(player), _) => player.id,
+            (None, Some(bullet)) => bullet.owner_id,
+            (None, None) => unreachable!("filtered on players and bullets"),
+        };
+
+        let layers = viewers
+            .iter()
+            .filter(|(viewer, viewer_transform)| {
+                viewer.id != owner_id
+                    && can_see(
+                        &terrain,
+                        viewer_transform.translation.xy(),
+                        transform.translation.xy(),
+                    )
+            })
+            .map(|(viewer, _)| sight_layer(viewer.id))
+            .fold(
+                RenderLayers::layer(sight_layer(owner_id)),
+                RenderLayers::with,
+            );
+
+        commands.entity(entity).insert(layers);
+    }
+}
+
+/// Lets the overview look at everything, but only for spectators and in debug mode
+#[allow(clippy::type_complexity)]
+pub fn set_overview_sight(
+    args: Res<Args>,
+    game_mode: Option<Res<GameMode>>,
+    mut cameras: Query<
+        &mut RenderLayers,
+        (
+            With<Camera2d>,
+            Without<CameraPosition>,
+            Without<IsDefaultUiCamera>,
+        ),
+    >,
+) {
+    let spectating = matches!(game_mode.as_deref(), Some(GameMode::Spectate(_)));
+
+    for mut layers in &mut cameras {
+        *layers = if args.debug || spectating {
+            (0..MAX_NUM_PLAYERS)
+                .flat_map(|id| [mine_layer(id), sight_layer(id)])
+                .fold(RenderLayers::layer(0), RenderLayers::with)
+        } else {
+            RenderLayers::layer(0)
+        };
+    }
+}
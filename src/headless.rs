@@ -0,0 +1,119 @@
+use crate::{
+    Config, GameStats, Terrain,
+    args::Args,
+    input::{INPUT_DOWN, INPUT_FIRE, INPUT_LEFT, INPUT_MINE, INPUT_RIGHT, INPUT_UP, INPUT_WEAPON},
+    network::DesyncCount,
+};
+use bevy::{platform::collections::HashMap, prelude::*};
+use bevy_ggrs::{LocalInputs, LocalPlayers, RollbackFrameCount, prelude::*};
+use std::time::Duration;
+
+/// How many frames past the reported frame have to be confirmed before reporting, so the peers
+/// have compared their checksums of the reported frame
+const DESYNC_CHECK_MARGIN: i32 = 60;
+/// How long to keep running after reporting, so the other peer receives our last inputs
+const EXIT_DELAY: Duration = Duration::from_secs(3);
+
+/// The state at the frame to report, recorded on every simulation of that frame, so after
+/// rollbacks it holds the confirmed state
+#[derive(Resource, Default, Debug)]
+pub struct FrameReport {
+    game_stats: GameStats,
+    stats_checksum: u64,
+    terrain_checksum: u64,
+    exit_timer: Option<Timer>,
+}
+
+pub fn headless_mode(args: Res<Args>) -> bool {
+    args.headless
+}
+
+/// Drives each tank in all eight directions in turn, in opposite order for each player, firing
+/// twice a second, switching weapons every other second and laying a mine every four seconds
+pub fn scripted_input(handle: usize, frame: i32) -> u8 {
+    const DIRECTIONS: [u8; 8] = [
+        INPUT_UP,
+        INPUT_UP | INPUT_RIGHT,
+        INPUT_RIGHT,
+        INPUT_DOWN | INPUT_RIGHT,
+        INPUT_DOWN,
+        INPUT_DOWN | INPUT_LEFT,
+        INPUT_LEFT,
+        INPUT_UP | INPUT_LEFT,
+    ];
+
+    let step = (frame / 60) as usize % DIRECTIONS.len();
+    let direction = match handle {
+        0 => DIRECTIONS[step],
+        _ => DIRECTIONS[(DIRECTIONS.len() - step) % DIRECTIONS.len()],
+    };
+    let fire = if frame % 30 == 0 { INPUT_FIRE } else { 0 };
+    let weapon = if frame % 120 == 90 { INPUT_WEAPON } else { 0 };
+    let mine = if frame % 240 == 200 { INPUT_MINE } else { 0 };
+
+    direction | fire | weapon | mine
+}
+
+pub fn read_scripted_inputs(
+    mut commands: Commands,
+    local_players: Res<LocalPlayers>,
+    frame: Res<RollbackFrameCount>,
+) {
+    let local_inputs: HashMap<_, _> = local_players
+        .0
+        .iter()
+        .map(|handle| (*handle, scripted_input(*handle, frame.0)))
+        .collect();
+
+    commands.insert_resource(LocalInputs::<Config>(local_inputs));
+}
+
+pub fn record_report(
+    args: Res<Args>,
+    frame: Res<RollbackFrameCount>,
+    game_stats: Res<GameStats>,
+    terrain: Res<Terrain>,
+    mut report: ResMut<FrameReport>,
+) {
+    if frame.0 != args.frames {
+        return;
+    }
+
+    report.stats_checksum = game_stats.checksum();
+
+    report.game_stats = game_stats.clone();
+    report.terrain_checksum = terrain.checksum();
+}
+
+/// Prints the report once the reported frame is confirmed, then exits
+pub fn report_and_exit(
+    time: Res<Time>,
+    args: Res<Args>,
+    session: Option<Res<Session<Config>>>,
+    desyncs: Res<DesyncCount>,
+    mut report: ResMut<FrameReport>,
+    mut exit: MessageWriter<AppExit>,
+) {
+    if let Some(timer) = &mut report.exit_timer {
+        if timer.tick(time.delta()).just_finished() {
+            exit.write(AppExit::Success);
+        }
+        return;
+    }
+
+    let Some(Session::P2P(session)) = session.as_deref() else {
+        return; // skip system: the match hasn't started
+    };
+
+    if session.confirmed_frame() < args.frames + DESYNC_CHECK_MARGIN {
+        return;
+    }
+
+    // the whole stats are printed to tell what differs, the checksums to compare at a glance
+    println!(
+        "REPORT frame={} stats={:016x} terrain={:016x} game_stats={:?} desyncs={}",
+        args.frames, report.stats_checksum, report.terrain_checksum, report.game_stats, **desyncs
+    );
+
+    report.exit_timer = Some(Timer::new(EXIT_DELAY, TimerMode::Once));
+}
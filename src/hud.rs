@@ -0,0 +1,95 @@
+use crate::{
+    COLOR_ENERGY, COLOR_SHIELD, COLOR_UI, GameStats, MAX_ENERGY, MAX_SHIELD, RoundClock,
+    components::{FireCooldown, Gauge, GaugeKind, Player, RoundClockLabel, Weapon, WeaponLabel},
+};
+use bevy::prelude::*;
+
+const HUD_HEIGHT: f32 = 24.0;
+
+/// The strip along the bottom of a player's viewport, with their gauges, weapon and the round time
+pub fn hud_strip(player_id: usize) -> impl Bundle {
+    (
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(0.0),
+            width: Val::Percent(100.0),
+            height: Val::Px(HUD_HEIGHT),
+            padding: UiRect::horizontal(Val::Px(10.0)),
+            column_gap: Val::Px(10.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::SpaceBetween,
+            ..default()
+        },
+        BackgroundColor(Color::BLACK.with_alpha(0.6)),
+        children![
+            gauge(player_id, GaugeKind::Energy, COLOR_ENERGY),
+            gauge(player_id, GaugeKind::Shield, COLOR_SHIELD),
+            (
+                WeaponLabel { player_id },
+                Text::new(""),
+                TextFont::from_font_size(14.0),
+            ),
+            gauge(player_id, GaugeKind::Reload, Color::WHITE),
+            (
+                RoundClockLabel,
+                Text::new(""),
+                TextFont::from_font_size(14.0),
+            ),
+        ],
+    )
+}
+
+fn gauge(player_id: usize, kind: GaugeKind, color: Color) -> impl Bundle {
+    (
+        Node {
+            flex_grow: 1.0,
+            height: Val::Px(8.0),
+            ..default()
+        },
+        BackgroundColor(COLOR_UI),
+        children![(
+            Gauge { player_id, kind },
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+            BackgroundColor(color),
+        )],
+    )
+}
+
+pub fn update_gauges(
+    players: Query<(&Player, &Weapon, &FireCooldown)>,
+    game_stats: Res<GameStats>,
+    mut gauges: Query<(&Gauge, &mut Node)>,
+) {
+    for (gauge, mut node) in &mut gauges {
+        let stats = &game_stats[gauge.player_id];
+
+        let fill = match gauge.kind {
+            GaugeKind::Energy => stats.energy as f32 / MAX_ENERGY as f32,
+            GaugeKind::Shield => stats.shield as f32 / MAX_SHIELD as f32,
+            GaugeKind::Reload => players
+                .iter()
+                .find(|(player, _, _)| player.id == gauge.player_id)
+                .map_or(0.0, |(_, weapon, cooldown)| {
+                    1.0 - cooldown.0 as f32 / weapon.kind.stats().cooldown as f32
+                }),
+        };
+
+        // the cooldown of the previous weapon can be longer than that of the current one
+        node.width = Val::Percent(fill.clamp(0.0, 1.0) * 100.0);
+    }
+}
+
+pub fn update_round_clocks(
+    clock: Res<RoundClock>,
+    mut labels: Query<&mut Text, With<RoundClockLabel>>,
+) {
+    let seconds = clock.elapsed().as_secs();
+
+    for mut text in &mut labels {
+        text.0 = format!("{}:{:02}", seconds / 60, seconds % 60);
+    }
+}
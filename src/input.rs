@@ -1,27 +1,62 @@
-use crate::{CameraMode, Config, args::Args};
+use crate::{
+    CameraMode, Config, GameMode,
+    args::Args,
+    bot::{self, BOT_HANDLE},
+    components::Player,
+    network::NetworkStatsVisible,
+};
 use bevy::{platform::collections::HashMap, prelude::*, window::WindowCloseRequested};
 use bevy_ggrs::{LocalInputs, LocalPlayers};
 
-const INPUT_UP: u8 = 1 << 0;
-const INPUT_DOWN: u8 = 1 << 1;
-const INPUT_LEFT: u8 = 1 << 2;
-const INPUT_RIGHT: u8 = 1 << 3;
-const INPUT_FIRE: u8 = 1 << 4;
+pub const INPUT_UP: u8 = 1 << 0;
+pub const INPUT_DOWN: u8 = 1 << 1;
+pub const INPUT_LEFT: u8 = 1 << 2;
+pub const INPUT_RIGHT: u8 = 1 << 3;
+pub const INPUT_FIRE: u8 = 1 << 4;
+pub const INPUT_WEAPON: u8 = 1 << 5;
+pub const INPUT_MINE: u8 = 1 << 6;
+pub const INPUT_SONAR: u8 = 1 << 7;
 
 const KEYS_UP: [KeyCode; 2] = [KeyCode::KeyW, KeyCode::ArrowUp];
 const KEYS_DOWN: [KeyCode; 2] = [KeyCode::KeyS, KeyCode::ArrowDown];
 const KEYS_LEFT: [KeyCode; 2] = [KeyCode::KeyA, KeyCode::ArrowLeft];
 const KEYS_RIGHT: [KeyCode; 2] = [KeyCode::KeyD, KeyCode::ArrowRight];
 const KEYS_FIRE: [KeyCode; 2] = [KeyCode::ControlLeft, KeyCode::Enter];
+const KEYS_WEAPON: [KeyCode; 2] = [KeyCode::KeyE, KeyCode::ShiftRight];
+const KEYS_MINE: [KeyCode; 2] = [KeyCode::KeyR, KeyCode::ControlRight];
+const KEYS_SONAR: [KeyCode; 2] = [KeyCode::KeyF, KeyCode::AltRight];
 
 pub fn read_local_inputs(
     mut commands: Commands,
     keys: Res<ButtonInput<KeyCode>>,
     local_players: Res<LocalPlayers>,
+    game_mode: Option<Res<GameMode>>,
+    players: Query<(&Player, &Transform)>,
 ) {
     let mut local_inputs = HashMap::new();
 
     for handle in &local_players.0 {
+        if *handle == BOT_HANDLE && game_mode.as_deref() == Some(&GameMode::Bot) {
+            let mut bot_position = None;
+            let mut target = None;
+
+            for (player, transform) in &players {
+                if player.id == BOT_HANDLE {
+                    bot_position = Some(transform.translation.xy());
+                } else {
+                    target = Some(transform.translation.xy());
+                }
+            }
+
+            let input = match bot_position {
+                Some(position) => bot::bot_input(position, target),
+                None => 0, // the bot's tank was destroyed
+            };
+
+            local_inputs.insert(*handle, input);
+            continue;
+        }
+
         let mut input = 0u8;
 
         if keys.pressed(KEYS_UP[*handle]) {
@@ -39,6 +74,15 @@ pub fn read_local_inputs(
         if keys.pressed(KEYS_FIRE[*handle]) {
             input |= INPUT_FIRE;
         }
+        if keys.pressed(KEYS_WEAPON[*handle]) {
+            input |= INPUT_WEAPON;
+        }
+        if keys.pressed(KEYS_MINE[*handle]) {
+            input |= INPUT_MINE;
+        }
+        if keys.pressed(KEYS_SONAR[*handle]) {
+            input |= INPUT_SONAR;
+        }
 
         local_inputs.insert(*handle, input);
     }
@@ -50,7 +94,9 @@ pub fn read_unsynced_inputs(
     keys: Res<ButtonInput<KeyCode>>,
     windows: Query<Entity, With<Window>>,
     args: Res<Args>,
+    game_mode: Option<Res<GameMode>>,
     mut camera_mode: ResMut<CameraMode>,
+    mut network_stats_visible: ResMut<NetworkStatsVisible>,
     mut messages: MessageWriter<WindowCloseRequested>,
 ) {
     if keys.all_pressed([KeyCode::ControlLeft, KeyCode::KeyQ]) {
@@ -60,10 +106,17 @@ pub fn read_unsynced_inputs(
         }
     }
 
-    if args.debug && keys.just_pressed(KeyCode::Tab) {
-        // toggle camera mode
+    let spectating = matches!(game_mode.as_deref(), Some(GameMode::Spectate(_)));
+
+    if (args.debug || spectating) && keys.just_pressed(KeyCode::Tab) {
+        // cycle camera mode
         *camera_mode = camera_mode.next();
     }
+
+    if args.debug && keys.just_pressed(KeyCode::F3) {
+        // toggle network statistics
+        **network_stats_visible = !**network_stats_visible;
+    }
 }
 
 pub fn direction(input: u8) -> Vec2 {
@@ -86,3 +139,15 @@ pub fn direction(input: u8) -> Vec2 {
 pub fn fire(input: u8) -> bool {
     input & INPUT_FIRE != 0
 }
+
+pub fn switch_weapon(input: u8) -> bool {
+    input & INPUT_WEAPON != 0
+}
+
+pub fn lay_mine(input: u8) -> bool {
+    input & INPUT_MINE != 0
+}
+
+pub fn sonar(input: u8) -> bool {
+    input & INPUT_SONAR != 0
+}
@@ -0,0 +1,169 @@
+use crate::protocol::Handshake;
+use bevy::{platform::collections::HashMap, prelude::*};
+use bevy_matchbox::prelude::*;
+use std::time::Duration;
+
+/// Unreliable channel that is handed over to GGRS when the match starts
+pub const GGRS_CHANNEL: usize = 0;
+/// Reliable channel for setting up the match with the other peers in the room
+pub const LOBBY_CHANNEL: usize = 1;
+
+/// How often players measure the round-trip time to each other while matchmaking
+pub const PING_INTERVAL: Duration = Duration::from_millis(100);
+/// How many round trips to measure before trusting the round-trip time
+const PING_SAMPLES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Player,
+    Spectator,
+}
+
+impl Role {
+    fn as_str(self) -> &'static str {
+        match self {
+            Role::Player => "player",
+            Role::Spectator => "spectator",
+        }
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        match text {
+            "player" => Some(Role::Player),
+            "spectator" => Some(Role::Spectator),
+            _ => None,
+        }
+    }
+}
+
+/// Messages sent over the lobby channel, encoded as short lines of text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LobbyMessage {
+    /// Sent to every peer that connects, announcing the role of the sender and what it runs
+    Hello { role: Role, handshake: Handshake },
+    /// Sent by the host to the other players and the spectators when the match starts
+    Start { seed: u64 },
+    /// Sent to spectators that join after the match has started, which can't take them on anymore
+    Started,
+    /// Asks the receiver to send back a pong, with the time it was sent in microseconds
+    Ping { sent: u64 },
+    /// Answers a ping, with the time copied from the ping
+    Pong { sent: u64 },
+}
+
+impl LobbyMessage {
+    pub fn encode(&self) -> Box<[u8]> {
+        let text = match self {
+            LobbyMessage::Hello { role, handshake } => format!(
+                "hello {} {} {:x} {:x}",
+                role.as_str(),
+                handshake.version,
+                handshake.rules_hash,
+                handshake.map_hash
+            ),
+            LobbyMessage::Start { seed } => format!("start {seed}"),
+            LobbyMessage::Started => String::from("started"),
+            LobbyMessage::Ping { sent } => format!("ping {sent}"),
+            LobbyMessage::Pong { sent } => format!("pong {sent}"),
+        };
+
+        text.into_bytes().into_boxed_slice()
+    }
+
+    pub fn decode(packet: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(packet).ok()?;
+        let (kind, value) = text.split_once(' ').unwrap_or((text, ""));
+
+        match kind {
+            "hello" => {
+                let mut fields = value.split(' ');
+                let role = Role::parse(fields.next()?)?;
+                let handshake = Handshake {
+                    version: String::from(fields.next()?),
+                    rules_hash: u64::from_str_radix(fields.next()?, 16).ok()?,
+                    map_hash: u64::from_str_radix(fields.next()?, 16).ok()?,
+                };
+                Some(LobbyMessage::Hello { role, handshake })
+            }
+            "start" => Some(LobbyMessage::Start {
+                seed: value.parse().ok()?,
+            }),
+            "started" => Some(LobbyMessage::Started),
+            "ping" => Some(LobbyMessage::Ping {
+                sent: value.parse().ok()?,
+            }),
+            "pong" => Some(LobbyMessage::Pong {
+                sent: value.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The roles of the peers in the room, as far as they've told us, and how far away they are
+#[derive(Resource, Default, Debug)]
+pub struct Lobby {
+    pub roles: HashMap<PeerId, Role>,
+    /// Peers we can't play with, and why
+    pub refused: HashMap<PeerId, String>,
+    pub round_trips: HashMap<PeerId, Vec<Duration>>,
+    pub last_ping: Option<Duration>,
+}
+
+impl Lobby {
+    /// Peers with the given role, in the same order on every peer
+    pub fn peers(&self, role: Role) -> Vec<PeerId> {
+        let mut peers: Vec<PeerId> = self
+            .roles
+            .iter()
+            .filter(|(_, r)| **r == role)
+            .map(|(peer, _)| *peer)
+            .collect();
+        peers.sort();
+        peers
+    }
+
+    /// The median round-trip time to the slowest of the given peers, once they've all been
+    /// measured often enough
+    pub fn round_trip_time(&self, peers: &[PeerId]) -> Option<Duration> {
+        let medians = peers.iter().map(|peer| {
+            let mut round_trips = self.round_trips.get(peer)?.clone();
+
+            if round_trips.len() < PING_SAMPLES {
+                return None;
+            }
+
+            round_trips.sort();
+            Some(round_trips[round_trips.len() / 2])
+        });
+
+        medians.collect::<Option<Vec<_>>>()?.into_iter().max()
+    }
+}
+
+/// Keeps answering pings once the match has started, for peers that are still measuring, and tells
+/// spectators that arrive too late that they've missed the start
+pub fn answer_pings(socket: Option<ResMut<MatchboxSocket>>) {
+    let Some(mut socket) = socket else {
+        return; // skip system: the session has ended
+    };
+
+    let Ok(channel) = socket.get_channel_mut(LOBBY_CHANNEL) else {
+        return;
+    };
+
+    for (peer, packet) in channel.receive() {
+        match LobbyMessage::decode(&packet) {
+            Some(LobbyMessage::Ping { sent }) => {
+                channel.send(LobbyMessage::Pong { sent }.encode(), peer);
+            }
+            Some(LobbyMessage::Hello {
+                role: Role::Spectator,
+                ..
+            }) => {
+                channel.send(LobbyMessage::Started.encode(), peer);
+            }
+            _ => {}
+        }
+    }
+}
@@ -1,48 +1,81 @@
 use crate::{
     args::Args,
     components::{
-        Bullet, BulletReady, CameraPosition, MoveDir, OnLoadingScreen, OnMatchmakingScreen, Player,
-        PlayerRef, checksum_transform,
+        Bullet, CameraPosition, Destroyed, FireCooldown, Mine, MineCooldown, MoveDir,
+        OnConnectionOverlay, OnGameScreen, OnLoadingScreen, OnMainMenuScreen, OnMatchmakingScreen,
+        OnNetworkStatsOverlay, OverviewUi, Player, PlayerRef, PowerUp, RoundBanner, Smoothing,
+        Weapon, WeaponLabel, checksum_transform,
     },
+    headless::{FrameReport, headless_mode},
     input::fire,
+    lobby::Lobby,
+    matchmaking::MatchmakingStatus,
+    menu::{MenuPage, MenuSelection},
+    network::{
+        ConnectionStatus, DEFAULT_INPUT_DELAY, DesyncCount, NetworkStatsVisible, ReceivedBytes,
+        RollbackCounter,
+    },
+    protocol::{Fnv1a, MatchRules},
+    smoothing::SimulationClock,
+    weapons::WeaponKind,
 };
 use bevy::{
+    app::{PluginGroupBuilder, ScheduleRunnerPlugin},
     asset::AssetMetaCheck,
-    camera::{ScalingMode, Viewport},
+    camera::{ScalingMode, visibility::RenderLayers},
     prelude::*,
-    render::sync_world::SyncToRenderWorld,
-    window::WindowTheme,
+    render::{RenderPlugin, settings::WgpuSettings},
+    time::Stopwatch,
+    window::{ExitCondition, WindowTheme},
+    winit::WinitPlugin,
 };
 use bevy_asset_loader::prelude::*;
-use bevy_ecs_tilemap::{FrustumCulling, prelude::*};
-use bevy_ggrs::{ggrs::DesyncDetection, prelude::*};
-use bevy_matchbox::{MatchboxSocket, prelude::PeerId};
-use bevy_roll_safe::prelude::*;
-use clap::Parser;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_ggrs::{LoadWorld, prelude::*};
+use bevy_matchbox::prelude::PeerId;
+use bevy_roll_safe::{InitialStateEntered, prelude::*};
 use fastrand::Rng;
+use std::{
+    hash::{Hash, Hasher},
+    time::Duration,
+};
 
 const MAX_NUM_PLAYERS: usize = 2;
+/// What the players are called on screen, after the colour of their tank
+const PLAYER_NAMES: [&str; MAX_NUM_PLAYERS] = ["Blue", "Green"];
 const TERRAIN_WIDTH: u32 = 500;
 const TERRAIN_HEIGHT: u32 = 250;
 
 // const COLOR_BLUE: Color = Color::srgb(0.173, 0.173, 1.0);
 // const COLOR_BLUE_DARK: Color = Color::srgb(0.0, 0.0, 0.714);
-// const COLOR_GREEN: Color = Color::srgb(0.0, 1.0, 0.0);
+const COLOR_GREEN: Color = Color::srgb(0.0, 1.0, 0.0);
 // const COLOR_GREEN_DARK: Color = Color::srgb(0.0, 0.667, 0.0);
 // const COLOR_TERRAIN_LIGHT: Color = Color::srgb(0.765, 0.475, 0.188);
 // const COLOR_TERRAIN_DARK: Color = Color::srgb(0.729, 0.349, 0.016);
 // const COLOR_ROCK: Color = Color::srgb(0.604, 0.604, 0.604);
-// const COLOR_ENERGY: Color = Color::srgb(0.915, 0.922, 0.110);
-// const COLOR_SHIELD: Color = Color::srgb(0.157, 0.953, 0.953);
-// const COLOR_UI: Color = Color::srgb(0.396, 0.396, 0.396);
+const COLOR_ENERGY: Color = Color::srgb(0.915, 0.922, 0.110);
+const COLOR_SHIELD: Color = Color::srgb(0.157, 0.953, 0.953);
+const COLOR_UI: Color = Color::srgb(0.396, 0.396, 0.396);
 const COLOR_BACKGROUND: Color = Color::srgb(0.0, 0.0, 0.179);
 
 const SPEED_MOVE_STANDARD: f32 = 14.0;
 const SPEED_BULLET: f32 = 98.0;
 
 const PLAYER_RADIUS: f32 = 2.5;
+/// Energy of a tank at the start of a round, which firing uses up
+const MAX_ENERGY: u32 = 100;
+/// Hits a tank can shrug off with shield boosts
+const MAX_SHIELD: u32 = 3;
 const BULLET_RADIUS: f32 = 0.5;
 
+/// Frames a bullet flies before it can hit its own tank, when self-hits are on
+const SELF_HIT_GRACE_FRAMES: u32 = 20;
+/// Seconds between a tank being destroyed and the next round
+const ROUND_END_DURATION: f32 = 2.0;
+/// Seconds the tanks wait at their spawn before a round starts
+const COUNTDOWN_DURATION: f32 = 3.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 enum TerrainType {
     Dark,
     Light,
@@ -50,15 +83,31 @@ enum TerrainType {
     Empty,
 }
 
-impl Into<TileTextureIndex> for TerrainType {
-    fn into(self) -> TileTextureIndex {
-        TileTextureIndex(self as u32)
+impl From<TerrainType> for TileTextureIndex {
+    fn from(terrain_type: TerrainType) -> Self {
+        TileTextureIndex(terrain_type as u32)
     }
 }
 
 mod args;
+mod bot;
 mod components;
+mod fog;
+mod headless;
+mod hud;
 mod input;
+mod lobby;
+mod matchmaking;
+mod menu;
+mod mines;
+mod network;
+mod power_ups;
+mod protocol;
+mod radar;
+mod smoothing;
+mod split_screen;
+mod visuals;
+mod weapons;
 
 type Config = GgrsConfig<u8, PeerId>;
 
@@ -78,129 +127,365 @@ struct ImageAssets {
 enum GameState {
     #[default]
     AssetLoading,
+    MainMenu,
     Matchmaking,
     InGame,
 }
 
 #[derive(States, Clone, Eq, PartialEq, Debug, Hash, Default, Reflect)]
 enum RollbackState {
-    /// When the characters running and gunning
+    /// When the characters wait for the countdown to the next round
     #[default]
+    RoundStart,
+    /// When the characters running and gunning
     InRound,
     /// When one character is dead, and we're transitioning to the next round
     RoundEnd,
 }
 
+/// The terrain type of every tile, column by column. The tiles are drawn from this, so that digging
+/// is rolled back along with the rest of the simulation.
+#[derive(Resource, Clone, Default)]
+struct Terrain(Vec<TerrainType>);
+
+impl Terrain {
+    /// Where the tile is stored, if it's on the map at all. Without checking both coordinates, a
+    /// tile past the top of one column would wrap around into the next.
+    fn index(pos: &TilePos) -> Option<usize> {
+        (pos.x < TERRAIN_WIDTH && pos.y < TERRAIN_HEIGHT)
+            .then(|| (pos.x * TERRAIN_HEIGHT + pos.y) as usize)
+    }
+
+    fn get(&self, pos: &TilePos) -> Option<TerrainType> {
+        Terrain::index(pos).and_then(|index| self.0.get(index).copied())
+    }
+
+    fn dig(&mut self, pos: &TilePos) {
+        if let Some(terrain_type) = Terrain::index(pos).and_then(|index| self.0.get_mut(index)) {
+            *terrain_type = TerrainType::Empty;
+        }
+    }
+
+    /// Hashes every tile as a single byte, so native and web peers get the same checksum
+    fn checksum(&self) -> u64 {
+        let mut hasher = Fnv1a::default();
+
+        for terrain_type in &self.0 {
+            hasher.write_u8(*terrain_type as u8);
+        }
+
+        hasher.finish()
+    }
+}
+
 #[derive(Resource, Clone, Deref, DerefMut)]
 struct RoundEndTimer(Timer);
 
 impl Default for RoundEndTimer {
     fn default() -> Self {
-        RoundEndTimer(Timer::from_seconds(2.0, TimerMode::Repeating))
+        RoundEndTimer(Timer::from_seconds(
+            ROUND_END_DURATION,
+            TimerMode::Repeating,
+        ))
+    }
+}
+
+/// Time since the countdown of the current round ended
+#[derive(Resource, Clone, Default, Deref, DerefMut)]
+struct RoundClock(Stopwatch);
+
+#[derive(Resource, Clone, Deref, DerefMut)]
+struct CountdownTimer(Timer);
+
+impl Default for CountdownTimer {
+    fn default() -> Self {
+        CountdownTimer(Timer::from_seconds(
+            COUNTDOWN_DURATION,
+            TimerMode::Repeating,
+        ))
     }
 }
 
 #[derive(Default, Clone, Copy, Debug)]
 struct PlayerStats {
-    score: u32, // TODO: add more stats later
+    score: u32,
+    /// Spent on firing, and refilled by energy cells
+    energy: u32,
+    /// Hits the tank can take before it's destroyed
+    shield: u32,
+    /// Frames left of a speed burst
+    speed_burst: u32,
+    /// Shots left of each weapon, apart from the cannon which never runs out
+    ammo: [u32; WeaponKind::ALL.len()],
+    /// Mines left to lay this round
+    mines: u32,
+    /// The tile the tank started the round on
+    base: UVec2,
+    /// Frames left that the opponent shows up on the radar
+    sonar: u32,
+}
+
+impl PlayerStats {
+    /// Takes away everything but the score, and fills up the energy
+    fn start_round(&mut self) {
+        *self = PlayerStats {
+            score: self.score,
+            energy: MAX_ENERGY,
+            mines: mines::MINES_PER_ROUND,
+            ..default()
+        };
+    }
+
+    fn has_ammo(&self, weapon: WeaponKind) -> bool {
+        weapon == WeaponKind::Standard || self.ammo[weapon as usize] > 0
+    }
+
+    /// Hashes every field as 32 bits, so native and web peers get the same checksum
+    fn write_checksum(&self, hasher: &mut Fnv1a) {
+        // taken apart, so a new field doesn't compile until it's hashed too
+        let PlayerStats {
+            score,
+            energy,
+            shield,
+            speed_burst,
+            ammo,
+            mines,
+            base,
+            sonar,
+        } = *self;
+
+        for value in [
+            score,
+            energy,
+            shield,
+            speed_burst,
+            mines,
+            base.x,
+            base.y,
+            sonar,
+        ]
+        .into_iter()
+        .chain(ammo)
+        {
+            hasher.write(&value.to_le_bytes());
+        }
+    }
+}
+
+/// How a round ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RoundOutcome {
+    /// Only this player's tank was left standing, by handle. A `u8` rather than a `usize`, so it's
+    /// the same size on native and web peers.
+    Won(u8),
+    /// No tank was left standing, because they were destroyed in the same frame
+    Draw,
 }
 
 #[derive(Resource, Clone, Deref, DerefMut, Default, Debug)]
-struct GameStats([PlayerStats; MAX_NUM_PLAYERS]);
+struct GameStats {
+    #[deref]
+    players: [PlayerStats; MAX_NUM_PLAYERS],
+    /// How the last round ended, until the next one starts
+    outcome: Option<RoundOutcome>,
+}
+
+impl GameStats {
+    /// Hashes the stats at fixed widths, unlike a derived hash, which hashes lengths and enum
+    /// variants at the width of the platform
+    fn checksum(&self) -> u64 {
+        let mut hasher = Fnv1a::default();
+
+        for stats in &self.players {
+            stats.write_checksum(&mut hasher);
+        }
+
+        match self.outcome {
+            None => hasher.write_u8(0),
+            Some(RoundOutcome::Won(winner)) => {
+                hasher.write_u8(1);
+                hasher.write_u8(winner);
+            }
+            Some(RoundOutcome::Draw) => hasher.write_u8(2),
+        }
+
+        hasher.finish()
+    }
+}
 
 #[derive(Resource, Default, Clone, Copy, Debug, Deref, DerefMut)]
 struct SessionSeed(u64);
 
+/// The random numbers of the simulation, seeded once per match from the session seed and rolled
+/// back with the rest of the game, so every peer draws the same map, power-ups and spawns
+#[derive(Resource, Clone, Deref, DerefMut)]
+struct RollbackRng(Rng);
+
+impl RollbackRng {
+    fn new(seed: SessionSeed) -> Self {
+        RollbackRng(Rng::with_seed(*seed))
+    }
+}
+
+impl Default for RollbackRng {
+    fn default() -> Self {
+        RollbackRng::new(SessionSeed::default())
+    }
+}
+
+impl Hash for RollbackRng {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.get_seed().hash(state);
+    }
+}
+
+/// How the match is set up, chosen in the main menu or with command line flags
+#[derive(Resource, Clone, Debug, PartialEq, Eq)]
+enum GameMode {
+    /// Online against whoever is next in line
+    QuickMatch,
+    /// Online in a new private room
+    CreateRoom,
+    /// Online in an existing private room
+    JoinRoom(String),
+    /// Watching the match in an existing private room
+    Spectate(String),
+    /// Two players sharing a keyboard
+    Local,
+    /// Against a computer-controlled opponent
+    Bot,
+    /// Local session that checks the simulation for determinism
+    SyncTest,
+}
+
+impl GameMode {
+    fn from_args(args: &Args) -> Option<Self> {
+        if args.synctest {
+            Some(GameMode::SyncTest)
+        } else if args.local {
+            Some(GameMode::Local)
+        } else if args.bot {
+            Some(GameMode::Bot)
+        } else if args.private {
+            Some(GameMode::CreateRoom)
+        } else if let Some(code) = &args.spectate {
+            Some(GameMode::Spectate(code.clone()))
+        } else if let Some(code) = &args.room {
+            Some(GameMode::JoinRoom(code.clone()))
+        } else if args.headless {
+            Some(GameMode::QuickMatch)
+        } else {
+            None
+        }
+    }
+
+    fn is_online(&self) -> bool {
+        matches!(
+            self,
+            GameMode::QuickMatch
+                | GameMode::CreateRoom
+                | GameMode::JoinRoom(_)
+                | GameMode::Spectate(_)
+        )
+    }
+}
+
 #[derive(Resource, Default, Clone, Copy, Debug)]
 enum CameraMode {
     #[default]
     Overview,
+    /// Split screen, following every tank
     Follow,
+    /// Full screen, following a single tank
+    FollowPlayer(usize),
 }
 
 impl CameraMode {
     fn next(self) -> Self {
         match self {
             CameraMode::Overview => CameraMode::Follow,
-            CameraMode::Follow => CameraMode::Overview,
+            CameraMode::Follow => CameraMode::FollowPlayer(0),
+            CameraMode::FollowPlayer(id) if id + 1 < MAX_NUM_PLAYERS => {
+                CameraMode::FollowPlayer(id + 1)
+            }
+            CameraMode::FollowPlayer(_) => CameraMode::Overview,
+        }
+    }
+
+    /// Whether the follow camera of the given player is shown
+    fn follows(self, player_id: usize) -> bool {
+        match self {
+            CameraMode::Overview => false,
+            CameraMode::Follow => true,
+            CameraMode::FollowPlayer(id) => id == player_id,
         }
     }
 }
 
 fn main() {
-    let args = Args::parse();
+    let args = Args::from_env();
 
     eprintln!("{args:#?}");
 
+    let plugins = if args.headless {
+        headless_plugins()
+    } else {
+        window_plugins()
+    };
+
     App::new()
         .add_plugins((
-            DefaultPlugins
-                .set(WindowPlugin {
-                    primary_window: Some(Window {
-                        fit_canvas_to_parent: true,
-                        prevent_default_event_handling: false,
-                        title: "Tunnel Tank Tournament".to_string(),
-                        window_theme: Some(WindowTheme::Dark),
-                        ..default()
-                    }),
-                    ..default()
-                })
-                .set(AssetPlugin {
-                    meta_check: AssetMetaCheck::Never,
-                    ..default()
-                })
-                .set(ImagePlugin::default_nearest()),
+            plugins,
             GgrsPlugin::<Config>::default(),
             RollbackSchedulePlugin::new_ggrs(),
-            TilemapPlugin,
         ))
         .init_state::<GameState>()
         .add_loading_state(
             LoadingState::new(GameState::AssetLoading)
                 .load_collection::<ImageAssets>()
-                .continue_to_state(GameState::Matchmaking),
+                .continue_to_state(GameState::MainMenu),
         )
         .init_ggrs_state::<RollbackState>()
         .rollback_resource_with_clone::<RoundEndTimer>()
+        .rollback_resource_with_clone::<CountdownTimer>()
+        .rollback_resource_with_clone::<RoundClock>()
         .rollback_resource_with_clone::<GameStats>()
+        .rollback_resource_with_clone::<Terrain>()
+        .rollback_resource_with_clone::<RollbackRng>()
         .rollback_component_with_clone::<Transform>()
-        .rollback_component_with_clone::<Sprite>()
         .rollback_component_with_copy::<Player>()
         .rollback_component_with_copy::<Bullet>()
-        .rollback_component_with_copy::<BulletReady>()
+        .rollback_component_with_copy::<FireCooldown>()
+        .rollback_component_with_copy::<Weapon>()
+        .rollback_component_with_copy::<PowerUp>()
+        .rollback_component_with_copy::<Mine>()
+        .rollback_component_with_copy::<MineCooldown>()
         .rollback_component_with_copy::<MoveDir>()
-        // Tilemap bundle components
-        .rollback_component_with_copy::<TilemapGridSize>()
-        .rollback_component_with_copy::<TilemapType>()
-        .rollback_component_with_copy::<TilemapSize>()
-        .rollback_component_with_copy::<TilemapSpacing>()
-        .rollback_component_with_clone::<TileStorage>()
-        .rollback_component_with_clone::<TilemapTexture>()
-        .rollback_component_with_copy::<TilemapTileSize>()
-        .rollback_component_with_copy::<Transform>()
-        .rollback_component_with_copy::<GlobalTransform>()
-        .rollback_component_with_copy::<TilemapRenderSettings>()
-        .rollback_component_with_copy::<Visibility>()
-        .rollback_component_with_copy::<InheritedVisibility>()
-        .rollback_component_with_copy::<ViewVisibility>()
-        .rollback_component_with_copy::<FrustumCulling>()
-        .rollback_component_with_clone::<MaterialTilemapHandle<StandardTilemapMaterial>>()
-        .rollback_component_with_copy::<SyncToRenderWorld>()
-        .rollback_component_with_copy::<TilemapAnchor>()
-        // Tile bundle components
-        .rollback_component_with_copy::<TilePos>()
-        .rollback_component_with_copy::<TileTextureIndex>()
-        .rollback_component_with_copy::<TilemapId>()
-        .rollback_component_with_copy::<TileVisible>()
-        .rollback_component_with_copy::<TileFlip>()
-        .rollback_component_with_copy::<TileColor>()
-        .rollback_component_with_copy::<TilePosOld>()
         .checksum_component::<Transform>(checksum_transform)
+        .checksum_resource::<Terrain>(Terrain::checksum)
+        .checksum_resource::<GameStats>(GameStats::checksum)
+        .checksum_resource_with_hash::<RollbackRng>()
         .insert_resource(args)
         .insert_resource(ClearColor(COLOR_BACKGROUND))
         .init_resource::<RoundEndTimer>()
+        .init_resource::<CountdownTimer>()
+        .init_resource::<RoundClock>()
         .init_resource::<GameStats>()
+        .init_resource::<Terrain>()
+        .init_resource::<RollbackRng>()
+        .init_resource::<MatchRules>()
         .init_resource::<CameraMode>()
+        .init_resource::<MatchmakingStatus>()
+        .init_resource::<Lobby>()
+        .init_resource::<ConnectionStatus>()
+        .init_resource::<DesyncCount>()
+        .init_resource::<FrameReport>()
+        .init_resource::<NetworkStatsVisible>()
+        .init_resource::<RollbackCounter>()
+        .init_resource::<ReceivedBytes>()
+        .init_resource::<SimulationClock>()
+        .init_resource::<MenuPage>()
+        .init_resource::<MenuSelection>()
         .add_systems(
             OnEnter(GameState::AssetLoading),
             (spawn_camera, show_loading_screen),
@@ -209,11 +494,23 @@ fn main() {
             OnExit(GameState::AssetLoading),
             clear_entities::<OnLoadingScreen>,
         )
+        .add_systems(OnEnter(GameState::MainMenu), menu::show_main_menu)
+        .add_systems(
+            OnExit(GameState::MainMenu),
+            (clear_entities::<OnMainMenuScreen>, menu::reset_main_menu),
+        )
+        .add_systems(
+            Update,
+            (menu::navigate_main_menu, menu::update_main_menu)
+                .chain()
+                .run_if(in_state(GameState::MainMenu)),
+        )
         .add_systems(
             OnEnter(GameState::Matchmaking),
             (
-                show_matchmaking_screen,
-                start_matchbox_socket.run_if(p2p_mode),
+                protocol::apply_match_rules,
+                matchmaking::show_matchmaking_screen,
+                matchmaking::start_matchbox_socket.run_if(p2p_mode),
             )
                 .chain(),
         )
@@ -224,41 +521,127 @@ fn main() {
         .add_systems(
             Update,
             (
-                set_camera_viewports,
+                split_screen::set_camera_viewports,
                 apply_camera_mode,
-                camera_follow,
+                camera_follow.after(smoothing::interpolate),
                 update_ui,
+                update_weapon_labels,
+                hud::update_gauges,
+                hud::update_round_clocks,
+                radar::update_radars,
+                update_round_banners.run_if(in_state(GameState::InGame)),
             ),
         )
+        .add_systems(
+            Update,
+            (
+                matchmaking::cancel_matchmaking,
+                matchmaking::request_start,
+                matchmaking::retry_matchbox_socket,
+                matchmaking::update_matchmaking_screen,
+            )
+                .chain()
+                .run_if(in_state(GameState::Matchmaking).and(p2p_mode)),
+        )
         .add_systems(
             FixedUpdate,
             (
-                wait_for_players.run_if(p2p_mode),
+                matchmaking::wait_for_players.run_if(p2p_mode),
                 start_synctest_session.run_if(synctest_mode),
             )
                 .run_if(in_state(GameState::Matchmaking)),
         )
         .add_systems(
             OnEnter(GameState::InGame),
-            (set_follow_camera, spawn_terrain, spawn_combined_ui_score),
+            (
+                (set_follow_camera, spawn_follow_cameras, spawn_round_banners).chain(),
+                fog::set_overview_sight,
+                spawn_terrain,
+                spawn_combined_ui_score,
+                network::spawn_connection_overlay,
+                network::spawn_network_stats_overlay,
+            ),
+        )
+        .add_systems(
+            OnExit(GameState::InGame),
+            (
+                network::teardown_session,
+                clear_entities::<Rollback>,
+                clear_entities::<TilePos>,
+                clear_entities::<TileStorage>,
+                clear_entities::<OnGameScreen>,
+                clear_entities::<OnConnectionOverlay>,
+                clear_entities::<OnNetworkStatsOverlay>,
+                reset_rollback_state,
+            ),
+        )
+        .add_systems(
+            Update,
+            (
+                update_terrain_tiles,
+                visuals::attach_sprites,
+                fog::reveal_in_sight,
+                smoothing::interpolate,
+                network::update_connection_overlay,
+                network::update_network_stats_overlay,
+                lobby::answer_pings,
+            )
+                .run_if(in_state(GameState::InGame)),
+        )
+        .add_systems(
+            LoadWorld,
+            (network::count_rollbacks, smoothing::note_rollback),
         )
         .add_systems(
             FixedUpdate,
             (
-                handle_ggrs_events.run_if(in_state(GameState::InGame)),
+                network::handle_ggrs_events
+                    .run_if(in_state(GameState::InGame).and(resource_exists::<Session<Config>>)),
                 input::read_unsynced_inputs,
             ),
         )
-        .add_systems(ReadInputs, input::read_local_inputs)
-        .add_systems(OnEnter(RollbackState::InRound), spawn_players)
+        .add_systems(
+            ReadInputs,
+            (
+                input::read_local_inputs.run_if(not(headless_mode)),
+                headless::read_scripted_inputs.run_if(headless_mode),
+            ),
+        )
+        .add_systems(
+            RollbackUpdate,
+            (
+                headless::record_report.run_if(headless_mode),
+                smoothing::record_frame,
+            )
+                .after(destroy_terrain)
+                .after(round_end_timeout),
+        )
+        .add_systems(
+            PostUpdate,
+            smoothing::apply_visual_transforms.after(TransformSystems::Propagate),
+        )
+        .add_systems(
+            Update,
+            headless::report_and_exit.run_if(headless_mode.and(in_state(GameState::InGame))),
+        )
+        .add_systems(OnEnter(RollbackState::RoundStart), spawn_players)
         .add_systems(
             RollbackUpdate,
             (
+                tick_round_clock,
                 move_players,
-                reload_bullet,
+                cool_down_weapons,
+                weapons::switch_weapons,
                 fire_bullets,
+                mines::lay_mines,
+                radar::ping_sonar,
                 move_bullet,
+                weapons::cancel_bullets,
                 destroy_players,
+                mines::trigger_mines,
+                end_round,
+                weapons::hit_terrain,
+                power_ups::collect_power_ups,
                 destroy_terrain,
             )
                 .chain()
@@ -266,19 +649,65 @@ fn main() {
         )
         .add_systems(
             RollbackUpdate,
-            round_end_timeout
-                .ambiguous_with(destroy_players)
-                .run_if(in_state(RollbackState::RoundEnd)),
+            (
+                count_down.run_if(in_state(RollbackState::RoundStart)),
+                round_end_timeout.run_if(in_state(RollbackState::RoundEnd)),
+            )
+                .chain()
+                .ambiguous_with(end_round)
+                .ambiguous_with(tick_round_clock),
         )
         .run();
 }
 
-fn synctest_mode(args: Res<Args>) -> bool {
-    args.synctest || args.local
+fn window_plugins() -> PluginGroupBuilder {
+    DefaultPlugins
+        .set(WindowPlugin {
+            primary_window: Some(Window {
+                fit_canvas_to_parent: true,
+                prevent_default_event_handling: false,
+                title: "Tunnel Tank Tournament".to_string(),
+                window_theme: Some(WindowTheme::Dark),
+                ..default()
+            }),
+            ..default()
+        })
+        .set(AssetPlugin {
+            meta_check: AssetMetaCheck::Never,
+            ..default()
+        })
+        .set(ImagePlugin::default_nearest())
+        .add(TilemapPlugin)
 }
 
-fn p2p_mode(args: Res<Args>) -> bool {
-    !args.synctest && !args.local
+/// Runs the simulation without a window or renderer, at the rate GGRS expects
+fn headless_plugins() -> PluginGroupBuilder {
+    DefaultPlugins
+        .set(WindowPlugin {
+            primary_window: None,
+            exit_condition: ExitCondition::DontExit,
+            ..default()
+        })
+        .set(RenderPlugin {
+            render_creation: WgpuSettings {
+                backends: None,
+                ..default()
+            }
+            .into(),
+            ..default()
+        })
+        .disable::<WinitPlugin>()
+        .add(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+            1.0 / 60.0,
+        )))
+}
+
+fn synctest_mode(game_mode: Option<Res<GameMode>>) -> bool {
+    game_mode.is_some_and(|mode| !mode.is_online())
+}
+
+fn p2p_mode(game_mode: Option<Res<GameMode>>) -> bool {
+    game_mode.is_some_and(|mode| mode.is_online())
 }
 
 fn show_loading_screen(mut commands: Commands) {
@@ -295,18 +724,19 @@ fn show_loading_screen(mut commands: Commands) {
     ));
 }
 
-fn show_matchmaking_screen(mut commands: Commands) {
+fn spawn_camera(mut commands: Commands, camera_mode: Res<CameraMode>) {
+    // draws screens and overlays on top of the whole window, but no game objects
     commands.spawn((
-        OnMatchmakingScreen,
-        Node {
-            margin: auto().all(),
+        Camera {
+            order: 3,
+            clear_color: ClearColorConfig::None,
             ..default()
         },
-        Text::new("Waiting for other players..."),
+        Camera2d,
+        IsDefaultUiCamera,
+        RenderLayers::none(),
     ));
-}
 
-fn spawn_camera(mut commands: Commands, camera_mode: Res<CameraMode>) {
     let camera_overview = commands
         .spawn((
             Camera {
@@ -323,61 +753,62 @@ fn spawn_camera(mut commands: Commands, camera_mode: Res<CameraMode>) {
                 },
                 ..OrthographicProjection::default_2d()
             }),
+            // what else the overview shows is decided when the match starts
+            RenderLayers::layer(0),
         ))
         .id();
     spawn_combined_ui(&mut commands, camera_overview);
+}
 
-    let camera0 = commands
-        .spawn((
-            Camera {
-                order: 0,
-                clear_color: ClearColorConfig::Custom(Color::BLACK),
-                is_active: matches!(camera_mode.as_ref(), CameraMode::Follow),
-                ..default()
-            },
-            Camera2d,
-            Projection::Orthographic(OrthographicProjection {
-                scaling_mode: ScalingMode::AutoMax {
-                    max_width: 76.0,
-                    max_height: 76.0,
-                },
-                ..OrthographicProjection::default_2d()
-            }),
-            CameraPosition {
-                pos: UVec2::new(0, 0),
-            },
-        ))
-        .id();
-
-    spawn_ui(&mut commands, camera0, 0);
+/// Spawns a follow camera for every player whose view is shown: online players only get their own,
+/// so they can't peek at where the opponent is digging
+fn spawn_follow_cameras(
+    mut commands: Commands,
+    camera_mode: Res<CameraMode>,
+    session: Option<Res<Session<Config>>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let player_ids = match session.as_deref() {
+        Some(Session::P2P(session)) => session.local_player_handles(),
+        _ => (0..MAX_NUM_PLAYERS).collect(),
+    };
 
-    let camera1 = commands
-        .spawn((
-            Camera {
-                order: 1,
-                clear_color: ClearColorConfig::Custom(Color::BLACK),
-                is_active: matches!(camera_mode.as_ref(), CameraMode::Follow),
-                ..default()
-            },
-            Camera2d,
-            Projection::Orthographic(OrthographicProjection {
-                scaling_mode: ScalingMode::AutoMax {
-                    max_width: 76.0,
-                    max_height: 76.0,
+    for player_id in player_ids {
+        let camera = commands
+            .spawn((
+                OnGameScreen,
+                Camera {
+                    order: player_id as isize,
+                    clear_color: ClearColorConfig::Custom(Color::BLACK),
+                    is_active: camera_mode.follows(player_id),
+                    ..default()
                 },
-                ..OrthographicProjection::default_2d()
-            }),
-            CameraPosition {
-                pos: UVec2::new(1, 0),
-            },
-        ))
-        .id();
-
-    spawn_ui(&mut commands, camera1, 1);
+                Camera2d,
+                Projection::Orthographic(OrthographicProjection {
+                    scaling_mode: ScalingMode::AutoMax {
+                        max_width: 76.0,
+                        max_height: 76.0,
+                    },
+                    ..OrthographicProjection::default_2d()
+                }),
+                CameraPosition {
+                    pos: UVec2::new(player_id as u32, 0),
+                },
+                RenderLayers::from_layers(&[
+                    0,
+                    mines::mine_layer(player_id),
+                    fog::sight_layer(player_id),
+                ]),
+            ))
+            .id();
+
+        spawn_ui(&mut commands, &mut images, camera, player_id);
+    }
 }
 
 fn spawn_combined_ui(commands: &mut Commands, camera_entity: Entity) {
     commands.spawn((
+        OverviewUi,
         UiTargetCamera(camera_entity),
         Node {
             width: Val::Percent(100.0),
@@ -387,12 +818,10 @@ fn spawn_combined_ui(commands: &mut Commands, camera_entity: Entity) {
     ));
 }
 
-fn spawn_combined_ui_score(
-    mut commands: Commands,
-    query: Query<Entity, (With<Node>, Without<PlayerRef>)>,
-) {
+fn spawn_combined_ui_score(mut commands: Commands, query: Query<Entity, With<OverviewUi>>) {
     let player0_ui = commands
         .spawn((
+            OnGameScreen,
             PlayerRef { id: 0 },
             Text::new("0"),
             Node {
@@ -406,6 +835,7 @@ fn spawn_combined_ui_score(
 
     let player1_ui = commands
         .spawn((
+            OnGameScreen,
             PlayerRef { id: 1 },
             Text::new("0"),
             Node {
@@ -424,24 +854,34 @@ fn spawn_combined_ui_score(
     }
 }
 
-fn spawn_ui(commands: &mut Commands, camera_entity: Entity, player_id: usize) {
+fn spawn_ui(
+    commands: &mut Commands,
+    images: &mut Assets<Image>,
+    camera_entity: Entity,
+    player_id: usize,
+) {
     commands.spawn((
+        OnGameScreen,
         UiTargetCamera(camera_entity),
         Node {
             width: Val::Percent(100.0),
             height: Val::Percent(100.0),
             ..default()
         },
-        children![(
-            PlayerRef { id: player_id },
-            Text::new("0"),
-            Node {
-                position_type: PositionType::Absolute,
-                top: Val::Px(10.0),
-                margin: auto().horizontal(),
-                ..default()
-            }
-        )],
+        children![
+            (
+                PlayerRef { id: player_id },
+                Text::new("0"),
+                Node {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(10.0),
+                    margin: auto().horizontal(),
+                    ..default()
+                }
+            ),
+            radar::radar_panel(player_id, images),
+            hud::hud_strip(player_id),
+        ],
     ));
 }
 
@@ -453,22 +893,107 @@ fn update_ui(game_stats: Res<GameStats>, mut query: Query<(&PlayerRef, &mut Text
     }
 }
 
-fn spawn_terrain(mut commands: Commands, images: Res<ImageAssets>) {
-    let mut rng = Rng::with_seed(42);
+fn update_weapon_labels(
+    players: Query<(&Player, &Weapon)>,
+    game_stats: Res<GameStats>,
+    mut labels: Query<(&WeaponLabel, &mut Text)>,
+) {
+    for (label, mut text) in &mut labels {
+        let weapon = players
+            .iter()
+            .find(|(player, _)| player.id == label.player_id)
+            .map(|(_, weapon)| weapon.kind);
+
+        text.0 = match weapon {
+            Some(WeaponKind::Standard) => String::from(WeaponKind::Standard.stats().name),
+            Some(kind) => format!(
+                "{} ({})",
+                kind.stats().name,
+                game_stats[label.player_id].ammo[kind as usize]
+            ),
+            None => String::new(),
+        };
+    }
+}
+
+/// Puts a banner in the middle of every viewport, for the countdown and the end of a round
+fn spawn_round_banners(
+    mut commands: Commands,
+    cameras: Query<Entity, (With<Camera2d>, Without<IsDefaultUiCamera>)>,
+) {
+    for camera in &cameras {
+        commands.spawn((
+            OnGameScreen,
+            UiTargetCamera(camera),
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            children![(
+                RoundBanner,
+                Text::new(""),
+                TextFont::from_font_size(32.0),
+                TextLayout::new_with_justify(Justify::Center),
+                Node {
+                    padding: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                },
+                BackgroundColor(COLOR_BACKGROUND.with_alpha(0.8)),
+                Visibility::Hidden,
+            )],
+        ));
+    }
+}
 
-    let map_size = TilemapSize {
-        x: TERRAIN_WIDTH,
-        y: TERRAIN_HEIGHT,
+fn update_round_banners(
+    state: Res<State<RollbackState>>,
+    countdown: Res<CountdownTimer>,
+    game_stats: Res<GameStats>,
+    mut banners: Query<(&mut Text, &mut Visibility), With<RoundBanner>>,
+) {
+    let message = match state.get() {
+        RollbackState::RoundStart => format!("{}", countdown.remaining_secs().ceil()),
+        RollbackState::InRound => String::new(),
+        RollbackState::RoundEnd => {
+            let headline = match game_stats.outcome {
+                Some(RoundOutcome::Won(id)) => {
+                    format!("{} wins the round!", PLAYER_NAMES[id as usize])
+                }
+                Some(RoundOutcome::Draw) => String::from("Draw!"),
+                None => String::new(),
+            };
+            let scores = game_stats
+                .iter()
+                .map(|stats| stats.score.to_string())
+                .collect::<Vec<_>>()
+                .join(" - ");
+
+            format!("{headline}\n{scores}")
+        }
     };
 
-    let tilemap_entity = commands.spawn_empty().id();
-    let mut tile_storage = TileStorage::empty(map_size);
+    for (mut text, mut visibility) in &mut banners {
+        *visibility = if message.is_empty() {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        text.0.clone_from(&message);
+    }
+}
+
+/// Generates the terrain type of every tile, column by column
+fn generate_terrain(rng: &mut Rng) -> Vec<(TilePos, TerrainType)> {
+    let mut terrain = Vec::with_capacity((TERRAIN_WIDTH * TERRAIN_HEIGHT) as usize);
 
     for x in 0..TERRAIN_WIDTH {
         for y in 0..TERRAIN_HEIGHT {
             let normalized_x = ((x as f32 / TERRAIN_WIDTH as f32 - 0.5).abs() * 2.0).powf(3.0);
             let normalized_y = ((y as f32 / TERRAIN_HEIGHT as f32 - 0.5).abs() * 2.0).powf(3.0);
-            let index = if rng.f32() < normalized_x || rng.f32() < normalized_y {
+            let terrain_type = if rng.f32() < normalized_x || rng.f32() < normalized_y {
                 TerrainType::Rock
             } else if rng.bool() {
                 TerrainType::Light
@@ -476,65 +1001,103 @@ fn spawn_terrain(mut commands: Commands, images: Res<ImageAssets>) {
                 TerrainType::Dark
             };
 
-            let tile_pos = TilePos { x, y };
+            terrain.push((TilePos { x, y }, terrain_type));
+        }
+    }
 
-            let tile_entity = commands
-                .spawn(TileBundle {
-                    texture_index: index.into(),
-                    position: tile_pos,
-                    tilemap_id: TilemapId(tilemap_entity),
-                    ..default()
-                })
-                .id();
+    terrain
+}
 
-            tile_storage.set(&tile_pos, tile_entity);
-        }
+/// Draws the map and its power-ups from the rollback RNG before the first frame is simulated, which
+/// happens the same way on every peer, so the rounds draw on from the same state
+fn spawn_terrain(mut commands: Commands, images: Res<ImageAssets>, mut rng: ResMut<RollbackRng>) {
+    let map_size = TilemapSize {
+        x: TERRAIN_WIDTH,
+        y: TERRAIN_HEIGHT,
+    };
+
+    let tilemap_entity = commands.spawn_empty().id();
+    let mut tile_storage = TileStorage::empty(map_size);
+    let terrain = generate_terrain(&mut rng);
+
+    commands.insert_resource(Terrain(
+        terrain
+            .iter()
+            .map(|(_, terrain_type)| *terrain_type)
+            .collect(),
+    ));
+    power_ups::spawn_power_ups(&mut commands, &terrain, &mut rng);
+
+    for (tile_pos, terrain_type) in terrain {
+        let tile_entity = commands
+            .spawn(TileBundle {
+                texture_index: terrain_type.into(),
+                position: tile_pos,
+                tilemap_id: TilemapId(tilemap_entity),
+                ..default()
+            })
+            .id();
+
+        tile_storage.set(&tile_pos, tile_entity);
     }
 
     let tile_size = TilemapTileSize { x: 1.0, y: 1.0 };
     let grid_size = tile_size.into();
     let map_type = TilemapType::Square;
 
-    commands
-        .entity(tilemap_entity)
-        .insert(TilemapBundle {
-            grid_size,
-            map_type,
-            size: map_size,
-            storage: tile_storage,
-            texture: TilemapTexture::Single(images.terrain.clone()),
-            tile_size,
-            anchor: TilemapAnchor::Center,
-            ..default()
-        })
-        .add_rollback();
+    commands.entity(tilemap_entity).insert(TilemapBundle {
+        grid_size,
+        map_type,
+        size: map_size,
+        storage: tile_storage,
+        texture: TilemapTexture::Single(images.terrain.clone()),
+        tile_size,
+        anchor: TilemapAnchor::Center,
+        ..default()
+    });
 }
 
-fn set_camera_viewports(windows: Query<&Window>, mut query: Query<(&CameraPosition, &mut Camera)>) {
-    for window in &windows {
-        let viewport_size = window.physical_size().x / 2 - 15;
-        let size = UVec2::splat(viewport_size);
-        let offset_y = (window.physical_size().y - viewport_size) / 2 + 10;
+/// Hides the tiles that have been dug out, and shows them again when that's rolled back
+fn update_terrain_tiles(
+    terrain: Res<Terrain>,
+    tile_storage: Query<&TileStorage>,
+    mut tiles: Query<&mut TileVisible>,
+    mut shown: Local<Terrain>,
+) {
+    let Ok(tile_storage) = tile_storage.single() else {
+        return; // skip system: the terrain isn't there yet
+    };
 
-        for (camera_position, mut camera) in &mut query {
-            let offset = UVec2::new(10 * (camera_position.pos.x + 1), offset_y);
+    // only look up the tiles that changed since last time, unless this is a new map
+    let new_map = shown.0.len() != terrain.0.len();
 
-            camera.viewport = Some(Viewport {
-                physical_position: camera_position.pos * size + offset,
-                physical_size: size,
-                ..default()
-            });
+    for (i, terrain_type) in terrain.0.iter().enumerate() {
+        if !new_map && shown.0[i] == *terrain_type {
+            continue;
+        }
+
+        let pos = TilePos {
+            x: i as u32 / TERRAIN_HEIGHT,
+            y: i as u32 % TERRAIN_HEIGHT,
+        };
+
+        if let Some(tile) = tile_storage.get(&pos)
+            && let Ok(mut visible) = tiles.get_mut(tile)
+        {
+            visible.0 = *terrain_type != TerrainType::Empty;
         }
     }
+
+    shown.0.clone_from(&terrain.0);
 }
 
 fn apply_camera_mode(
     camera_mode: Res<CameraMode>,
-    mut follow_cameras: Query<&mut Camera, With<CameraPosition>>,
-    mut overview_cameras: Query<&mut Camera, Without<CameraPosition>>,
+    mut follow_cameras: Query<(&mut Camera, &CameraPosition)>,
+    mut overview_cameras: Query<&mut Camera, (Without<CameraPosition>, Without<IsDefaultUiCamera>)>,
 ) {
-    for mut camera in follow_cameras.iter_mut() {
-        camera.is_active = matches!(*camera_mode, CameraMode::Follow);
+    for (mut camera, position) in follow_cameras.iter_mut() {
+        camera.is_active = camera_mode.follows(position.pos.x as usize);
     }
 
     for mut camera in overview_cameras.iter_mut() {
@@ -542,15 +1105,22 @@ fn apply_camera_mode(
     }
 }
 
-fn set_follow_camera(mut camera_mode: ResMut<CameraMode>) {
-    *camera_mode = CameraMode::Follow;
+fn set_follow_camera(mut camera_mode: ResMut<CameraMode>, game_mode: Option<Res<GameMode>>) {
+    // spectators start with an overview of the whole match
+    if !matches!(game_mode.as_deref(), Some(GameMode::Spectate(_))) {
+        *camera_mode = CameraMode::Follow;
+    }
 }
 
+#[allow(clippy::type_complexity)]
 fn camera_follow(
-    players: Query<(&Player, &Transform)>,
+    players: Query<(&Player, &Transform, Option<&Smoothing>)>,
     mut cameras: Query<(&mut Transform, &CameraPosition), (With<Camera2d>, Without<Player>)>,
 ) {
-    for (player, player_transform) in &players {
+    for (player, player_transform, smoothing) in &players {
+        // follow the tank where it's drawn, so it doesn't judder on screen
+        let player_transform = smoothing.map_or(player_transform, |smoothing| &smoothing.visual);
+
         for (mut transform, position) in &mut cameras {
             if position.pos.x as usize != player.id {
                 // skip if the camera is for another player
@@ -568,10 +1138,16 @@ fn spawn_players(
     mut commands: Commands,
     players: Query<Entity, With<Player>>,
     bullets: Query<Entity, With<Bullet>>,
-    images: Res<ImageAssets>,
-    session_seed: Res<SessionSeed>,
+    mines: Query<Entity, With<Mine>>,
+    mut rng: ResMut<RollbackRng>,
+    mut game_stats: ResMut<GameStats>,
 ) {
-    // prepare next round by despawning all existing players and bullets
+    for stats in game_stats.iter_mut() {
+        stats.start_round();
+    }
+    game_stats.outcome = None;
+
+    // prepare next round by despawning all existing players, bullets and mines
     for player in &players {
         commands.entity(player).despawn();
     }
@@ -580,7 +1156,9 @@ fn spawn_players(
         commands.entity(bullet).despawn();
     }
 
-    let mut rng = Rng::with_seed(**session_seed);
+    for mine in &mines {
+        commands.entity(mine).despawn();
+    }
 
     let p0_pos = Vec3::new(
         (rng.f32() - 0.5) * TERRAIN_WIDTH as f32,
@@ -594,16 +1172,20 @@ fn spawn_players(
         10.0,
     );
 
+    for (stats, pos) in game_stats.iter_mut().zip([p0_pos, p1_pos]) {
+        stats.base = UVec2::new(
+            (pos.x + TERRAIN_WIDTH as f32 / 2.0).floor() as u32,
+            (pos.y + TERRAIN_HEIGHT as f32 / 2.0).floor() as u32,
+        );
+    }
+
     commands
         .spawn((
             Player { id: 0 },
-            BulletReady(true),
+            FireCooldown::default(),
+            MineCooldown::default(),
+            Weapon::default(),
             Transform::from_translation(p0_pos),
-            Sprite {
-                image: images.tank_blue.clone(),
-                custom_size: Some(Vec2::new(5.0, 7.0)),
-                ..default()
-            },
             MoveDir(Vec2::Y),
         ))
         .add_rollback();
@@ -611,13 +1193,10 @@ fn spawn_players(
     commands
         .spawn((
             Player { id: 1 },
-            BulletReady(true),
+            FireCooldown::default(),
+            MineCooldown::default(),
+            Weapon::default(),
             Transform::from_translation(p1_pos),
-            Sprite {
-                image: images.tank_green.clone(),
-                custom_size: Some(Vec2::new(5.0, 7.0)),
-                ..default()
-            },
             MoveDir(Vec2::Y),
         ))
         .add_rollback();
@@ -626,19 +1205,28 @@ fn spawn_players(
 fn move_players(
     mut players: Query<(&mut Transform, &Player, &mut MoveDir)>,
     inputs: Res<PlayerInputs<Config>>,
+    mut game_stats: ResMut<GameStats>,
     time: Res<Time>,
 ) {
     for (mut transform, player, mut move_dir) in &mut players {
         let (input, _) = inputs[player.id];
         let direction = input::direction(input);
 
+        let stats = &mut game_stats[player.id];
+        let speed = if stats.speed_burst > 0 {
+            SPEED_MOVE_STANDARD * power_ups::SPEED_BURST_FACTOR
+        } else {
+            SPEED_MOVE_STANDARD
+        };
+        stats.speed_burst = stats.speed_burst.saturating_sub(1);
+
         if direction == Vec2::ZERO {
             continue;
         }
 
         move_dir.0 = direction;
 
-        let move_delta = direction * SPEED_MOVE_STANDARD * time.delta_secs();
+        let move_delta = direction * speed * time.delta_secs();
 
         let old_pos = transform.translation.xy();
         let limit = Vec2::new(
@@ -657,15 +1245,16 @@ fn start_synctest_session(
     mut commands: Commands,
     mut next_state: ResMut<NextState<GameState>>,
     args: Res<Args>,
+    game_mode: Res<GameMode>,
 ) {
     info!("Starting synctest session");
     let num_players = 2;
 
     let mut session_builder = SessionBuilder::<Config>::new()
         .with_num_players(num_players)
-        .with_input_delay(args.input_delay);
+        .with_input_delay(args.input_delay.unwrap_or(DEFAULT_INPUT_DELAY));
 
-    if args.local {
+    if matches!(*game_mode, GameMode::Local | GameMode::Bot) {
         session_builder = session_builder.with_input_delay(0).with_check_distance(0);
     }
 
@@ -680,170 +1269,172 @@ fn start_synctest_session(
         .expect("failed to start session");
 
     commands.insert_resource(bevy_ggrs::Session::SyncTest(ggrs_session));
-    commands.insert_resource(SessionSeed(Rng::new().u64(0..=u64::MAX)));
-    next_state.set(GameState::InGame);
-}
-
-fn start_matchbox_socket(mut commands: Commands, args: Res<Args>) {
-    let args_txt = if args.debug { "-debug" } else { "" };
-    let room_url = format!("{}/tunnel{}?next=2", args.match_url, args_txt);
-    info!("Connecting to matchbox room at: {}", room_url);
-    commands.insert_resource(MatchboxSocket::new_unreliable(room_url));
-}
-
-fn wait_for_players(
-    mut commands: Commands,
-    mut socket: ResMut<MatchboxSocket>,
-    mut next_state: ResMut<NextState<GameState>>,
-    args: Res<Args>,
-) {
-    if socket.get_channel(0).is_err() {
-        return; // skip system: we've already started
-    }
-
-    socket.update_peers();
-    let players = socket.players();
-
-    if players.len() < MAX_NUM_PLAYERS {
-        return; // wait for more players
-    }
-
-    info!("All players connected, starting game!");
-
-    let id = socket
-        .id()
-        .expect("failed to get local peer ID")
-        .0
-        .as_u64_pair();
-    let mut seed = id.0 ^ id.1;
-
-    for peer in socket.connected_peers() {
-        let peer_id = peer.0.as_u64_pair();
-        seed ^= peer_id.0 ^ peer_id.1;
-    }
-
-    commands.insert_resource(SessionSeed(seed));
-
-    // create a GGRS P2P session
-    let mut session_builder = SessionBuilder::<Config>::new()
-        .with_num_players(players.len())
-        .with_desync_detection_mode(DesyncDetection::On { interval: 1 })
-        .with_input_delay(args.input_delay);
-
-    for (i, player) in players.into_iter().enumerate() {
-        session_builder = session_builder
-            .add_player(player, i)
-            .expect("failed to add player");
-    }
-
-    // move the channel out of the socket (required because GGRS takes ownership of it)
-    let channel = socket.take_channel(0).unwrap();
-
-    // start the GGRS session
-    let ggrs_session = session_builder
-        .start_p2p_session(channel)
-        .expect("failed to start session");
-
-    commands.insert_resource(Session::P2P(ggrs_session));
+    let seed = SessionSeed(Rng::new().u64(0..=u64::MAX));
+    commands.insert_resource(seed);
+    commands.insert_resource(RollbackRng::new(seed));
     next_state.set(GameState::InGame);
 }
 
 fn fire_bullets(
     mut commands: Commands,
     inputs: Res<PlayerInputs<Config>>,
-    images: Res<ImageAssets>,
-    mut players: Query<(&Transform, &Player, &mut BulletReady, &MoveDir)>,
+    mut players: Query<(
+        &Transform,
+        &Player,
+        &mut FireCooldown,
+        &MoveDir,
+        &mut Weapon,
+    )>,
+    bullets: Query<&Bullet>,
+    mut game_stats: ResMut<GameStats>,
 ) {
-    for (transform, player, mut bullet_ready, move_dir) in &mut players {
-        if fire(inputs[player.id].0) && bullet_ready.0 {
-            commands
-                .spawn((
-                    Bullet {
-                        owner_id: player.id,
-                    },
-                    Transform::from_translation(transform.translation)
-                        .with_rotation(Quat::from_rotation_arc_2d(Vec2::Y, move_dir.0)),
-                    *move_dir,
-                    Sprite {
-                        image: images.bullet.clone(),
-                        custom_size: Some(Vec2::new(1.0, 2.0)),
-                        ..default()
-                    },
-                ))
-                .add_rollback();
+    for (transform, player, mut cooldown, move_dir, mut weapon) in &mut players {
+        let stats = weapon.kind.stats();
+        let player_stats = &mut game_stats[player.id];
+        let live_bullets = bullets
+            .iter()
+            .filter(|bullet| bullet.owner_id == player.id)
+            .count();
+
+        if fire(inputs[player.id].0)
+            && cooldown.0 == 0
+            && live_bullets + stats.projectiles as usize <= weapons::MAX_LIVE_BULLETS
+            && player_stats.energy >= stats.energy
+            && player_stats.has_ammo(weapon.kind)
+        {
+            for direction in weapons::shot_directions(stats, move_dir.0) {
+                commands
+                    .spawn((
+                        Bullet {
+                            owner_id: player.id,
+                            weapon: weapon.kind,
+                            penetration: stats.penetration,
+                            age: 0,
+                        },
+                        Transform::from_translation(transform.translation)
+                            .with_rotation(Quat::from_rotation_arc_2d(Vec2::Y, direction)),
+                        MoveDir(direction),
+                    ))
+                    .add_rollback();
+            }
+
+            cooldown.0 = stats.cooldown;
+            player_stats.energy -= stats.energy;
 
-            bullet_ready.0 = false;
+            if weapon.kind != WeaponKind::Standard {
+                player_stats.ammo[weapon.kind as usize] -= 1;
+
+                if !player_stats.has_ammo(weapon.kind) {
+                    weapon.kind = WeaponKind::Standard;
+                }
+            }
         }
     }
 }
 
-fn reload_bullet(
-    inputs: Res<PlayerInputs<Config>>,
-    mut players: Query<(&mut BulletReady, &Player)>,
-) {
-    for (mut can_fire, player) in players.iter_mut() {
-        let (input, _) = inputs[player.id];
-        if !fire(input) {
-            can_fire.0 = true;
-        }
+fn cool_down_weapons(mut players: Query<(&mut FireCooldown, &mut MineCooldown)>) {
+    for (mut fire_cooldown, mut mine_cooldown) in &mut players {
+        fire_cooldown.0 = fire_cooldown.0.saturating_sub(1);
+        mine_cooldown.0 = mine_cooldown.0.saturating_sub(1);
     }
 }
 
-fn move_bullet(mut bullets: Query<(&mut Transform, &MoveDir), With<Bullet>>, time: Res<Time>) {
-    for (mut transform, move_dir) in &mut bullets {
-        let delta = move_dir.0 * SPEED_BULLET * time.delta_secs();
+fn move_bullet(mut bullets: Query<(&mut Transform, &MoveDir, &mut Bullet)>, time: Res<Time>) {
+    for (mut transform, move_dir, mut bullet) in &mut bullets {
+        let delta = move_dir.0 * bullet.weapon.stats().speed * time.delta_secs();
         transform.translation += delta.extend(0.0);
+        bullet.age += 1;
     }
 }
 
 fn destroy_players(
     mut commands: Commands,
-    players: Query<(Entity, &Player, &Transform)>,
-    bullets: Query<(&Bullet, &Transform)>,
-    mut next_state: ResMut<NextState<RollbackState>>,
+    players: Query<(Entity, &Player, &Transform), Without<Destroyed>>,
+    bullets: Query<(Entity, &Bullet, &Transform)>,
     mut game_stats: ResMut<GameStats>,
+    rules: Res<MatchRules>,
 ) {
     for (entity, player, player_transform) in &players {
-        for (bullet, bullet_transform) in &bullets {
+        for (bullet_entity, bullet, bullet_transform) in &bullets {
             let distance = Vec2::distance(
                 player_transform.translation.xy(),
                 bullet_transform.translation.xy(),
             );
-            if distance < PLAYER_RADIUS + BULLET_RADIUS && bullet.owner_id != player.id as usize {
-                commands.entity(entity).despawn();
-                next_state.set(RollbackState::RoundEnd);
-                game_stats[bullet.owner_id].score += 1;
-
-                info!(
-                    "Player {} hit Player {}! Scores: {:?}",
-                    bullet.owner_id, player.id, &**game_stats
-                );
+            let radius = PLAYER_RADIUS + bullet.weapon.stats().radius;
+            let hits_owner = rules.self_hits && bullet.age > SELF_HIT_GRACE_FRAMES;
+            if distance < radius && (bullet.owner_id != player.id || hits_owner) {
+                if game_stats[player.id].shield > 0 {
+                    // the shield takes the hit, and the bullet with it
+                    game_stats[player.id].shield -= 1;
+                    commands.entity(bullet_entity).try_despawn();
+                    continue;
+                }
+
+                commands.entity(entity).insert(Destroyed);
+
+                info!("Player {} hit Player {}!", bullet.owner_id, player.id);
+
+                break; // any other bullets hit a wreck
             }
         }
     }
 }
 
-fn destroy_terrain(
-    players: Query<&Transform, With<Player>>,
-    mut tile_storage: Query<&mut TileStorage>,
-    mut visibility_query: Query<&mut TileVisible>,
+/// Ends the round once tanks have been destroyed, after everything that can destroy them in this
+/// frame has had its go, so tanks that destroy each other at the same time make it a draw
+fn end_round(
+    mut commands: Commands,
+    players: Query<(Entity, &Player, Has<Destroyed>)>,
+    mut next_state: ResMut<NextState<RollbackState>>,
+    mut game_stats: ResMut<GameStats>,
+    rules: Res<MatchRules>,
 ) {
+    let mut survivors = Vec::new();
+    let mut destroyed = false;
+
+    for (entity, player, is_destroyed) in &players {
+        if is_destroyed {
+            commands.entity(entity).despawn();
+            destroyed = true;
+        } else {
+            survivors.push(player.id);
+        }
+    }
+
+    if !destroyed {
+        return;
+    }
+
+    let outcome = match survivors[..] {
+        [winner] => RoundOutcome::Won(winner as u8),
+        _ => RoundOutcome::Draw,
+    };
+
+    match outcome {
+        RoundOutcome::Won(winner) => game_stats[winner as usize].score += 1,
+        RoundOutcome::Draw if rules.draw_points => {
+            for stats in game_stats.iter_mut() {
+                stats.score += 1;
+            }
+        }
+        RoundOutcome::Draw => {}
+    }
+
+    game_stats.outcome = Some(outcome);
+    next_state.set(RollbackState::RoundEnd);
+
+    info!("Round over, {outcome:?}! Scores: {:?}", game_stats.players);
+}
+
+fn destroy_terrain(players: Query<&Transform, With<Player>>, mut terrain: ResMut<Terrain>) {
     for player_transform in &players {
         let player_tile = TilePos {
             x: (player_transform.translation.x + TERRAIN_WIDTH as f32 / 2.0).floor() as u32,
             y: (player_transform.translation.y + TERRAIN_HEIGHT as f32 / 2.0).floor() as u32,
         };
 
-        let neighbors = get_neighbors_in_radius(&player_tile, 2);
-
-        for tile_storage in &mut tile_storage {
-            for neighbor in neighbors.iter() {
-                if let Some(tile_entity) = tile_storage.get(neighbor) {
-                    if let Ok(mut visibility) = visibility_query.get_mut(tile_entity) {
-                        visibility.0 = false;
-                    }
-                }
-            }
+        for neighbor in get_neighbors_in_radius(&player_tile, 2) {
+            terrain.dig(&neighbor);
         }
     }
 }
@@ -875,38 +1466,47 @@ fn get_neighbors_in_radius(pos: &TilePos, radius: u32) -> Vec<TilePos> {
 fn round_end_timeout(
     mut timer: ResMut<RoundEndTimer>,
     mut state: ResMut<NextState<RollbackState>>,
+    mut round_clock: ResMut<RoundClock>,
     time: Res<Time>,
 ) {
     timer.tick(time.delta());
 
     if timer.just_finished() {
-        state.set(RollbackState::InRound);
+        state.set(RollbackState::RoundStart);
+        round_clock.reset();
     }
 }
 
-fn handle_ggrs_events(mut session: ResMut<Session<Config>>) {
-    if let Session::P2P(s) = session.as_mut() {
-        for event in s.events() {
-            match event {
-                GgrsEvent::Disconnected { .. } | GgrsEvent::NetworkInterrupted { .. } => {
-                    warn!("GGRS event: {event:?}")
-                }
-                GgrsEvent::DesyncDetected {
-                    local_checksum,
-                    remote_checksum,
-                    frame,
-                    ..
-                } => {
-                    error!(
-                        "Desync on frame {frame}. Local checksum: {local_checksum:X}, remote checksum: {remote_checksum:X}"
-                    );
-                }
-                _ => info!("GGRS event: {event:?}"),
-            }
-        }
+fn tick_round_clock(mut clock: ResMut<RoundClock>, time: Res<Time>) {
+    clock.tick(time.delta());
+}
+
+/// Counts down to the start of the round, while the tanks wait at their spawn
+fn count_down(
+    mut timer: ResMut<CountdownTimer>,
+    mut state: ResMut<NextState<RollbackState>>,
+    time: Res<Time>,
+) {
+    timer.tick(time.delta());
+
+    if timer.just_finished() {
+        state.set(RollbackState::InRound);
     }
 }
 
+/// Puts the rollback state back to how it was at startup, so the next match starts fresh
+fn reset_rollback_state(mut commands: Commands) {
+    commands.insert_resource(State::new(RollbackState::default()));
+    commands.insert_resource(NextState::<RollbackState>::Unchanged);
+    commands.insert_resource(InitialStateEntered::<RollbackState>::default());
+    commands.insert_resource(RoundEndTimer::default());
+    commands.insert_resource(CountdownTimer::default());
+    commands.insert_resource(RoundClock::default());
+    commands.insert_resource(GameStats::default());
+    commands.insert_resource(RollbackRng::default());
+    commands.insert_resource(CameraMode::default());
+}
+
 fn clear_entities<T: Component>(mut commands: Commands, query: Query<Entity, With<T>>) {
     for entity in &query {
         commands.entity(entity).despawn_children();
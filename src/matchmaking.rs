@@ -0,0 +1,521 @@
+use crate::{
+    Config, GameMode, GameState, MAX_NUM_PLAYERS, RollbackRng, SessionSeed,
+    args::Args,
+    components::OnMatchmakingScreen,
+    lobby::{GGRS_CHANNEL, LOBBY_CHANNEL, Lobby, LobbyMessage, PING_INTERVAL, Role},
+    network::{
+        DISCONNECT_NOTIFY_DELAY, DISCONNECT_TIMEOUT, MeteredChannel, ReceivedBytes, input_delay_for,
+    },
+    protocol::Handshake,
+};
+use bevy::{prelude::*, time::Stopwatch};
+use bevy_ggrs::{ggrs::DesyncDetection, prelude::*};
+use bevy_matchbox::prelude::*;
+use fastrand::Rng;
+use std::time::Duration;
+
+const RETRY_DELAY_INITIAL: Duration = Duration::from_secs(1);
+const RETRY_DELAY_MAX: Duration = Duration::from_secs(30);
+
+const KEY_CANCEL: KeyCode = KeyCode::Escape;
+const KEY_START: KeyCode = KeyCode::Enter;
+
+/// Code of the private room to play in, shared out-of-band with the other players
+#[derive(Resource, Clone, Debug, Deref)]
+pub struct RoomCode(String);
+
+impl RoomCode {
+    fn generate() -> Self {
+        let alphabet: Vec<char> = crate::args::ROOM_CODE_ALPHABET.chars().collect();
+        let mut rng = Rng::new();
+        RoomCode(
+            (0..crate::args::ROOM_CODE_LENGTH)
+                .map(|_| alphabet[rng.usize(..alphabet.len())])
+                .collect(),
+        )
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalingState {
+    /// Waiting for the signaling server to assign us an id
+    #[default]
+    Connecting,
+    /// Registered with the signaling server, waiting for peers
+    Connected,
+    /// The socket failed and we're waiting before opening a new one
+    Retrying,
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct MatchmakingStatus {
+    pub signaling: SignalingState,
+    /// Players in the room that we know of, including ourselves unless we're spectating
+    pub players: usize,
+    /// Round-trip time to the other players, once it's been measured
+    pub round_trip_time: Option<Duration>,
+    /// Why we refused to play with a peer in the room, if we did
+    pub refused: Option<String>,
+    /// Spectators in the room that we know of
+    pub spectators: usize,
+    /// Whether everyone is there and we're the one to start the match in a private room
+    pub can_start: bool,
+    /// Whether the host of a private room asked to start the match, by key or by message
+    pub start_requested: bool,
+    /// Whether we came to spectate a match that had already started
+    pub too_late: bool,
+    pub failed_attempts: u32,
+    pub elapsed: Stopwatch,
+    pub retry_timer: Timer,
+}
+
+impl MatchmakingStatus {
+    /// Exponential back-off: 1, 2, 4, ... seconds, capped at `RETRY_DELAY_MAX`
+    fn retry_delay(&self) -> Duration {
+        let factor = 2u32.saturating_pow(self.failed_attempts.saturating_sub(1));
+        RETRY_DELAY_INITIAL
+            .saturating_mul(factor)
+            .min(RETRY_DELAY_MAX)
+    }
+}
+
+pub fn show_matchmaking_screen(mut commands: Commands) {
+    commands.spawn((
+        OnMatchmakingScreen,
+        Node {
+            margin: auto().all(),
+            ..default()
+        },
+        Text::new("Waiting for other players..."),
+        TextLayout::new_with_justify(Justify::Center),
+    ));
+}
+
+pub fn update_matchmaking_screen(
+    time: Res<Time>,
+    game_mode: Res<GameMode>,
+    room_code: Option<Res<RoomCode>>,
+    mut status: ResMut<MatchmakingStatus>,
+    mut query: Query<&mut Text, With<OnMatchmakingScreen>>,
+) {
+    status.elapsed.tick(time.delta());
+
+    let mut lines = Vec::new();
+
+    let spectating = matches!(*game_mode, GameMode::Spectate(_));
+    let private = matches!(*game_mode, GameMode::CreateRoom | GameMode::JoinRoom(_));
+
+    if let Some(code) = room_code {
+        let action = if spectating { "Spectating" } else { "Private" };
+        lines.push(format!("{action} room: {}\n", **code));
+    }
+
+    match status.signaling {
+        SignalingState::Connecting => lines.push(String::from("Connecting to server...")),
+        SignalingState::Connected if status.too_late => lines.push(String::from(
+            "The match in this room has already started, so it can't be watched anymore.",
+        )),
+        SignalingState::Connected if spectating => {
+            lines.push(String::from("Waiting for the match to start..."))
+        }
+        SignalingState::Connected if status.can_start => {
+            lines.push(format!("Press {KEY_START:?} to start the match"))
+        }
+        SignalingState::Connected if private && status.players >= MAX_NUM_PLAYERS => {
+            lines.push(String::from("Waiting for the host to start the match..."))
+        }
+        SignalingState::Connected => lines.push(String::from("Waiting for other players...")),
+        SignalingState::Retrying => lines.push(format!(
+            "Connection failed, retrying in {}s (attempt {})...",
+            status.retry_timer.remaining().as_secs() + 1,
+            status.failed_attempts + 1,
+        )),
+    }
+
+    lines.push(format!("Players: {}/{MAX_NUM_PLAYERS}", status.players));
+
+    if status.spectators > 0 {
+        lines.push(format!("Spectators: {}", status.spectators));
+    }
+
+    if let Some(round_trip_time) = status.round_trip_time {
+        lines.push(format!("Ping: {} ms", round_trip_time.as_millis()));
+    }
+
+    if let Some(reason) = &status.refused {
+        lines.push(format!(
+            "\nCan't play with someone in this room: {reason}.\nReload the game if you're out of date."
+        ));
+    }
+
+    lines.push(format!("Time: {}s", status.elapsed.elapsed().as_secs()));
+    lines.push(format!("\nPress {KEY_CANCEL:?} to return to the menu"));
+
+    for mut text in &mut query {
+        text.0 = lines.join("\n");
+    }
+}
+
+pub fn start_matchbox_socket(mut commands: Commands, args: Res<Args>, game_mode: Res<GameMode>) {
+    commands.insert_resource(MatchmakingStatus::default());
+    commands.insert_resource(Lobby::default());
+
+    let room_code = match game_mode.as_ref() {
+        GameMode::CreateRoom => Some(RoomCode::generate()),
+        GameMode::JoinRoom(code) | GameMode::Spectate(code) => Some(RoomCode(code.clone())),
+        _ => None,
+    };
+
+    open_socket(&mut commands, &args, room_code.as_ref());
+
+    if let Some(room_code) = room_code {
+        commands.insert_resource(room_code);
+    }
+}
+
+/// Opens a socket to the matchbox room. Quick matches pair up the next players in line, while
+/// private rooms connect everyone in them, so spectators can join the players.
+fn open_socket(commands: &mut Commands, args: &Args, room_code: Option<&RoomCode>) {
+    let args_txt = if args.debug { "-debug" } else { "" };
+    let room_url = match room_code {
+        Some(code) => format!("{}/tunnel-{}{}", args.match_url, **code, args_txt),
+        None => format!(
+            "{}/tunnel{}?next={}",
+            args.match_url, args_txt, MAX_NUM_PLAYERS
+        ),
+    };
+    info!("Connecting to matchbox room at: {}", room_url);
+    commands.open_socket(
+        WebRtcSocketBuilder::new(room_url)
+            .add_unreliable_channel()
+            .add_reliable_channel(),
+    );
+}
+
+pub fn retry_matchbox_socket(
+    mut commands: Commands,
+    time: Res<Time>,
+    args: Res<Args>,
+    room_code: Option<Res<RoomCode>>,
+    mut status: ResMut<MatchmakingStatus>,
+    mut lobby: ResMut<Lobby>,
+) {
+    if status.signaling != SignalingState::Retrying {
+        return;
+    }
+
+    status.retry_timer.tick(time.delta());
+
+    if status.retry_timer.just_finished() {
+        status.signaling = SignalingState::Connecting;
+        *lobby = Lobby::default();
+        open_socket(&mut commands, &args, room_code.as_deref());
+    }
+}
+
+pub fn cancel_matchmaking(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keys.just_pressed(KEY_CANCEL) {
+        info!("Matchmaking cancelled");
+        commands.close_socket();
+        commands.remove_resource::<RoomCode>();
+        commands.remove_resource::<GameMode>();
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+/// Lets the host of a private room start the match, once the spectators are in
+pub fn request_start(keys: Res<ButtonInput<KeyCode>>, mut status: ResMut<MatchmakingStatus>) {
+    if status.can_start && keys.just_pressed(KEY_START) {
+        status.start_requested = true;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn wait_for_players(
+    mut commands: Commands,
+    socket: Option<ResMut<MatchboxSocket>>,
+    mut lobby: ResMut<Lobby>,
+    mut status: ResMut<MatchmakingStatus>,
+    mut next_state: ResMut<NextState<GameState>>,
+    time: Res<Time<Real>>,
+    args: Res<Args>,
+    game_mode: Res<GameMode>,
+    handshake: Res<Handshake>,
+) {
+    let Some(mut socket) = socket else {
+        return; // skip system: the socket is closed while retrying
+    };
+
+    if socket.get_channel(GGRS_CHANNEL).is_err() {
+        return; // skip system: we've already started
+    }
+
+    let Ok(peer_changes) = socket.try_update_peers() else {
+        status.failed_attempts += 1;
+        let delay = status.retry_delay();
+        warn!(
+            "Matchbox socket closed, retrying in {}s",
+            delay.as_secs_f32()
+        );
+        status.signaling = SignalingState::Retrying;
+        status.players = 0;
+        status.round_trip_time = None;
+        status.refused = None;
+        status.retry_timer = Timer::new(delay, TimerMode::Once);
+        commands.close_socket();
+        return;
+    };
+
+    let local_role = match *game_mode {
+        GameMode::Spectate(_) => Role::Spectator,
+        _ => Role::Player,
+    };
+
+    for (peer, state) in peer_changes {
+        match state {
+            PeerState::Connected => {
+                let hello = LobbyMessage::Hello {
+                    role: local_role,
+                    handshake: handshake.clone(),
+                };
+                socket.channel_mut(LOBBY_CHANNEL).send(hello.encode(), peer);
+            }
+            PeerState::Disconnected => {
+                lobby.roles.remove(&peer);
+                lobby.refused.remove(&peer);
+            }
+        }
+    }
+
+    let now = time.elapsed();
+    let mut start = None;
+
+    for (peer, packet) in socket.channel_mut(LOBBY_CHANNEL).receive() {
+        match LobbyMessage::decode(&packet) {
+            Some(LobbyMessage::Hello {
+                role,
+                handshake: remote_handshake,
+            }) => match handshake.mismatch(&remote_handshake) {
+                None => {
+                    lobby.roles.insert(peer, role);
+                }
+                Some(reason) => {
+                    warn!("Refusing to play with {peer}: {reason}");
+                    lobby.refused.insert(peer, reason);
+                }
+            },
+            Some(LobbyMessage::Start { seed }) if lobby.roles.contains_key(&peer) => {
+                start = Some((peer, seed));
+            }
+            Some(LobbyMessage::Start { .. }) => {
+                warn!("Ignoring match started by {peer}, because we refused them");
+            }
+            Some(LobbyMessage::Started) if local_role == Role::Spectator => {
+                warn!("The match was started by {peer} before we joined");
+                status.too_late = true;
+            }
+            Some(LobbyMessage::Started) => {}
+            Some(LobbyMessage::Ping { sent }) => {
+                let pong = LobbyMessage::Pong { sent };
+                socket.channel_mut(LOBBY_CHANNEL).send(pong.encode(), peer);
+            }
+            Some(LobbyMessage::Pong { sent }) => {
+                let round_trip = now.saturating_sub(Duration::from_micros(sent));
+                lobby.round_trips.entry(peer).or_default().push(round_trip);
+            }
+            None => warn!("Ignoring invalid lobby message from {peer}"),
+        }
+    }
+
+    if local_role == Role::Player
+        && lobby
+            .last_ping
+            .is_none_or(|last_ping| now - last_ping >= PING_INTERVAL)
+    {
+        lobby.last_ping = Some(now);
+
+        for peer in lobby.peers(Role::Player) {
+            let ping = LobbyMessage::Ping {
+                sent: now.as_micros() as u64,
+            };
+            socket.channel_mut(LOBBY_CHANNEL).send(ping.encode(), peer);
+        }
+    }
+
+    let Some(local_id) = socket.id() else {
+        return; // wait for the signaling server
+    };
+
+    status.signaling = SignalingState::Connected;
+
+    let mut player_ids = lobby.peers(Role::Player);
+
+    if local_role == Role::Player {
+        player_ids.push(local_id);
+        player_ids.sort();
+    }
+
+    status.players = player_ids.len();
+    status.spectators = lobby.peers(Role::Spectator).len();
+    status.refused = lobby.refused.values().next().cloned();
+
+    let remote_player_ids: Vec<PeerId> = player_ids
+        .iter()
+        .filter(|id| **id != local_id)
+        .copied()
+        .collect();
+    status.round_trip_time = lobby.round_trip_time(&remote_player_ids);
+    status.can_start = false;
+
+    match local_role {
+        Role::Player if player_ids.len() >= MAX_NUM_PLAYERS => {
+            player_ids.truncate(MAX_NUM_PLAYERS);
+
+            if !player_ids.contains(&local_id) {
+                return; // the room is full, wait for a player to leave
+            }
+
+            // private rooms wait for the host, so spectators can come in until then
+            if matches!(*game_mode, GameMode::CreateRoom | GameMode::JoinRoom(_)) {
+                let host = player_ids[0];
+
+                if host == local_id {
+                    status.can_start = true;
+                } else if start.is_some_and(|(peer, _)| peer == host) {
+                    status.start_requested = true;
+                }
+
+                if !status.start_requested {
+                    return;
+                }
+            }
+
+            let input_delay = match (args.input_delay, status.round_trip_time) {
+                (Some(input_delay), _) => input_delay,
+                (None, Some(round_trip_time)) => {
+                    let input_delay = input_delay_for(round_trip_time);
+                    info!(
+                        "Round-trip time is {}ms, using an input delay of {input_delay} frames",
+                        round_trip_time.as_millis()
+                    );
+                    input_delay
+                }
+                (None, None) => return, // wait until the connection is measured
+            };
+
+            start_p2p_session(&mut commands, &mut socket, &lobby, &player_ids, input_delay);
+            next_state.set(GameState::InGame);
+        }
+        Role::Spectator => {
+            if let Some((host, seed)) = start {
+                start_spectator_session(&mut commands, &mut socket, host, seed);
+                next_state.set(GameState::InGame);
+            }
+        }
+        Role::Player => {} // wait for more players
+    }
+}
+
+fn start_p2p_session(
+    commands: &mut Commands,
+    socket: &mut MatchboxSocket,
+    lobby: &Lobby,
+    player_ids: &[PeerId],
+    input_delay: usize,
+) {
+    info!("All players connected, starting game!");
+
+    let local_id = socket.id().expect("failed to get local peer ID");
+
+    let seed = player_ids.iter().fold(0, |seed, id| {
+        let id = id.0.as_u64_pair();
+        seed ^ id.0 ^ id.1
+    });
+
+    commands.insert_resource(SessionSeed(seed));
+    commands.insert_resource(RollbackRng::new(SessionSeed(seed)));
+
+    // create a GGRS P2P session
+    let mut session_builder = SessionBuilder::<Config>::new()
+        .with_num_players(player_ids.len())
+        .with_desync_detection_mode(DesyncDetection::On { interval: 1 })
+        .with_disconnect_timeout(DISCONNECT_TIMEOUT)
+        .with_disconnect_notify_delay(DISCONNECT_NOTIFY_DELAY)
+        .with_input_delay(input_delay);
+
+    for (i, id) in player_ids.iter().enumerate() {
+        let player = if *id == local_id {
+            PlayerType::Local
+        } else {
+            PlayerType::Remote(*id)
+        };
+
+        session_builder = session_builder
+            .add_player(player, i)
+            .expect("failed to add player");
+    }
+
+    // the first player hosts the spectators, by sending them the confirmed inputs
+    if player_ids[0] == local_id {
+        let start = LobbyMessage::Start { seed };
+
+        for (i, spectator) in lobby.peers(Role::Spectator).into_iter().enumerate() {
+            info!("Adding spectator {spectator}");
+
+            session_builder = session_builder
+                .add_player(PlayerType::Spectator(spectator), player_ids.len() + i)
+                .expect("failed to add spectator");
+
+            socket
+                .channel_mut(LOBBY_CHANNEL)
+                .send(start.encode(), spectator);
+        }
+
+        // in private rooms, the other players wait for this before they start
+        for player in player_ids.iter().filter(|id| **id != local_id) {
+            socket
+                .channel_mut(LOBBY_CHANNEL)
+                .send(start.encode(), *player);
+        }
+    }
+
+    // move the channel out of the socket (required because GGRS takes ownership of it)
+    let received = ReceivedBytes::default();
+    let channel = MeteredChannel::new(socket.take_channel(GGRS_CHANNEL).unwrap(), &received);
+    commands.insert_resource(received);
+
+    // start the GGRS session
+    let ggrs_session = session_builder
+        .start_p2p_session(channel)
+        .expect("failed to start session");
+
+    commands.insert_resource(Session::P2P(ggrs_session));
+}
+
+fn start_spectator_session(
+    commands: &mut Commands,
+    socket: &mut MatchboxSocket,
+    host: PeerId,
+    seed: u64,
+) {
+    info!("Match started by {host}, spectating!");
+
+    commands.insert_resource(SessionSeed(seed));
+    commands.insert_resource(RollbackRng::new(SessionSeed(seed)));
+
+    let received = ReceivedBytes::default();
+    let channel = MeteredChannel::new(socket.take_channel(GGRS_CHANNEL).unwrap(), &received);
+    commands.insert_resource(received);
+
+    let ggrs_session = SessionBuilder::<Config>::new()
+        .with_num_players(MAX_NUM_PLAYERS)
+        .with_disconnect_timeout(DISCONNECT_TIMEOUT)
+        .with_disconnect_notify_delay(DISCONNECT_NOTIFY_DELAY)
+        .start_spectator_session(host, channel);
+
+    commands.insert_resource(Session::Spectator(ggrs_session));
+}
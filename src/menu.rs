@@ -0,0 +1,289 @@
+use crate::{
+    GameMode, GameState,
+    args::{Args, ROOM_CODE_ALPHABET, ROOM_CODE_LENGTH},
+    components::OnMainMenuScreen,
+    network::MAX_INPUT_DELAY,
+};
+use bevy::{
+    input::{
+        ButtonState,
+        keyboard::{Key, KeyboardInput},
+    },
+    prelude::*,
+};
+
+const KEYS_MENU_UP: [KeyCode; 2] = [KeyCode::ArrowUp, KeyCode::KeyW];
+const KEYS_MENU_DOWN: [KeyCode; 2] = [KeyCode::ArrowDown, KeyCode::KeyS];
+const KEYS_MENU_LEFT: [KeyCode; 2] = [KeyCode::ArrowLeft, KeyCode::KeyA];
+const KEYS_MENU_RIGHT: [KeyCode; 2] = [KeyCode::ArrowRight, KeyCode::KeyD];
+const KEYS_MENU_SELECT: [KeyCode; 2] = [KeyCode::Enter, KeyCode::Space];
+const KEY_MENU_BACK: KeyCode = KeyCode::Escape;
+
+#[derive(Resource, Default, Debug, Clone, PartialEq, Eq)]
+pub enum MenuPage {
+    #[default]
+    Main,
+    /// Typing the code of a private room, holding the characters typed so far
+    JoinRoom(String),
+    /// Typing the code of a private room to watch
+    SpectateRoom(String),
+    Settings,
+}
+
+#[derive(Resource, Default, Debug, Clone, Copy, Deref, DerefMut)]
+pub struct MenuSelection(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MenuItem {
+    QuickMatch,
+    CreateRoom,
+    JoinRoom,
+    SpectateRoom,
+    Local,
+    Bot,
+    Settings,
+    InputDelay,
+    SelfHits,
+    DrawPoints,
+    Debug,
+    Back,
+}
+
+impl MenuPage {
+    fn items(&self) -> &'static [MenuItem] {
+        match self {
+            MenuPage::Main => &[
+                MenuItem::QuickMatch,
+                MenuItem::CreateRoom,
+                MenuItem::JoinRoom,
+                MenuItem::SpectateRoom,
+                MenuItem::Local,
+                MenuItem::Bot,
+                MenuItem::Settings,
+            ],
+            MenuPage::JoinRoom(_) | MenuPage::SpectateRoom(_) => &[],
+            MenuPage::Settings => &[
+                MenuItem::InputDelay,
+                MenuItem::SelfHits,
+                MenuItem::DrawPoints,
+                MenuItem::Debug,
+                MenuItem::Back,
+            ],
+        }
+    }
+
+    fn position(&self, item: MenuItem) -> usize {
+        self.items().iter().position(|i| *i == item).unwrap_or(0)
+    }
+}
+
+impl MenuItem {
+    fn label(self, args: &Args) -> String {
+        match self {
+            MenuItem::QuickMatch => String::from("Quick match (online)"),
+            MenuItem::CreateRoom => String::from("Create private room"),
+            MenuItem::JoinRoom => String::from("Join private room"),
+            MenuItem::SpectateRoom => String::from("Watch private room"),
+            MenuItem::Local => String::from("Local two-player"),
+            MenuItem::Bot => String::from("Versus bot"),
+            MenuItem::Settings => String::from("Settings"),
+            MenuItem::InputDelay => match args.input_delay {
+                Some(input_delay) => format!("< Input delay: {input_delay} frames >"),
+                None => String::from("< Input delay: auto >"),
+            },
+            MenuItem::SelfHits => {
+                format!("Self-hits: {}", if args.self_hits { "on" } else { "off" })
+            }
+            MenuItem::DrawPoints => format!(
+                "Draws: {}",
+                if args.draw_points {
+                    "a point each"
+                } else {
+                    "no points"
+                }
+            ),
+            MenuItem::Debug => format!("Debug mode: {}", if args.debug { "on" } else { "off" }),
+            MenuItem::Back => String::from("Back"),
+        }
+    }
+}
+
+/// Shows the main menu, or skips it the first time when the game mode was chosen on the command
+/// line
+pub fn show_main_menu(
+    mut commands: Commands,
+    args: Res<Args>,
+    mut visited: Local<bool>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !*visited {
+        *visited = true;
+
+        if let Some(game_mode) = GameMode::from_args(&args) {
+            commands.insert_resource(game_mode);
+            next_state.set(GameState::Matchmaking);
+            return;
+        }
+    }
+
+    commands.spawn((
+        OnMainMenuScreen,
+        Node {
+            margin: auto().all(),
+            ..default()
+        },
+        Text::new(""),
+        TextLayout::new_with_justify(Justify::Center),
+    ));
+}
+
+pub fn reset_main_menu(mut page: ResMut<MenuPage>, mut selection: ResMut<MenuSelection>) {
+    *page = MenuPage::Main;
+    **selection = 0;
+}
+
+pub fn navigate_main_menu(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut keyboard_inputs: MessageReader<KeyboardInput>,
+    mut args: ResMut<Args>,
+    mut page: ResMut<MenuPage>,
+    mut selection: ResMut<MenuSelection>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let spectating = matches!(*page, MenuPage::SpectateRoom(_));
+
+    if let MenuPage::JoinRoom(code) | MenuPage::SpectateRoom(code) = page.as_mut() {
+        for input in keyboard_inputs.read() {
+            if input.state != ButtonState::Pressed {
+                continue;
+            }
+
+            match &input.logical_key {
+                Key::Character(c) => {
+                    for c in c.to_uppercase().chars() {
+                        if code.len() < ROOM_CODE_LENGTH && ROOM_CODE_ALPHABET.contains(c) {
+                            code.push(c);
+                        }
+                    }
+                }
+                Key::Backspace => {
+                    code.pop();
+                }
+                _ => {}
+            }
+        }
+
+        if keys.any_just_pressed(KEYS_MENU_SELECT) && code.len() == ROOM_CODE_LENGTH {
+            commands.insert_resource(if spectating {
+                GameMode::Spectate(code.clone())
+            } else {
+                GameMode::JoinRoom(code.clone())
+            });
+            next_state.set(GameState::Matchmaking);
+        } else if keys.just_pressed(KEY_MENU_BACK) {
+            *page = MenuPage::Main;
+            **selection = page.position(if spectating {
+                MenuItem::SpectateRoom
+            } else {
+                MenuItem::JoinRoom
+            });
+        }
+
+        return;
+    }
+
+    keyboard_inputs.clear();
+
+    let items = page.items();
+
+    if keys.any_just_pressed(KEYS_MENU_UP) {
+        **selection = (**selection + items.len() - 1) % items.len();
+    }
+    if keys.any_just_pressed(KEYS_MENU_DOWN) {
+        **selection = (**selection + 1) % items.len();
+    }
+
+    let item = items[**selection % items.len()];
+
+    if let MenuItem::InputDelay = item {
+        // ordered as: auto, 0, 1, ..., MAX_INPUT_DELAY
+        if keys.any_just_pressed(KEYS_MENU_LEFT) {
+            args.input_delay = args.input_delay.and_then(|delay| delay.checked_sub(1));
+        }
+        if keys.any_just_pressed(KEYS_MENU_RIGHT) {
+            args.input_delay = Some(
+                args.input_delay
+                    .map_or(0, |delay| (delay + 1).min(MAX_INPUT_DELAY)),
+            );
+        }
+    }
+
+    let item = if keys.just_pressed(KEY_MENU_BACK) && *page == MenuPage::Settings {
+        MenuItem::Back
+    } else if keys.any_just_pressed(KEYS_MENU_SELECT) {
+        item
+    } else {
+        return;
+    };
+
+    let mut start = |mode: GameMode| {
+        info!("Starting {mode:?}");
+        commands.insert_resource(mode);
+        next_state.set(GameState::Matchmaking);
+    };
+
+    match item {
+        MenuItem::QuickMatch => start(GameMode::QuickMatch),
+        MenuItem::CreateRoom => start(GameMode::CreateRoom),
+        MenuItem::Local => start(GameMode::Local),
+        MenuItem::Bot => start(GameMode::Bot),
+        MenuItem::JoinRoom => *page = MenuPage::JoinRoom(String::new()),
+        MenuItem::SpectateRoom => *page = MenuPage::SpectateRoom(String::new()),
+        MenuItem::Settings => {
+            *page = MenuPage::Settings;
+            **selection = 0;
+        }
+        MenuItem::InputDelay => {
+            args.input_delay = match args.input_delay {
+                None => Some(0),
+                Some(delay) if delay < MAX_INPUT_DELAY => Some(delay + 1),
+                Some(_) => None,
+            }
+        }
+        MenuItem::SelfHits => args.self_hits = !args.self_hits,
+        MenuItem::DrawPoints => args.draw_points = !args.draw_points,
+        MenuItem::Debug => args.debug = !args.debug,
+        MenuItem::Back => {
+            *page = MenuPage::Main;
+            **selection = page.position(MenuItem::Settings);
+        }
+    }
+}
+
+pub fn update_main_menu(
+    args: Res<Args>,
+    page: Res<MenuPage>,
+    selection: Res<MenuSelection>,
+    mut query: Query<&mut Text, With<OnMainMenuScreen>>,
+) {
+    let mut lines = vec![String::from("TUNNEL TANK TOURNAMENT\n")];
+
+    match page.as_ref() {
+        MenuPage::JoinRoom(code) | MenuPage::SpectateRoom(code) => {
+            let placeholder = "_".repeat(ROOM_CODE_LENGTH - code.len());
+            lines.push(format!("Room code: {code}{placeholder}\n"));
+            lines.push(String::from("Press Enter to join, Escape to go back"));
+        }
+        page => {
+            for (i, item) in page.items().iter().enumerate() {
+                let cursor = if i == **selection { ">" } else { " " };
+                lines.push(format!("{cursor} {}", item.label(&args)));
+            }
+        }
+    }
+
+    for mut text in &mut query {
+        text.0 = lines.join("\n");
+    }
+}
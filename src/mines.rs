@@ -0,0 +1,110 @@
+use crate::{
+    Config, GameStats, PLAYER_RADIUS, TERRAIN_HEIGHT, TERRAIN_WIDTH, Terrain,
+    components::{Destroyed, Mine, MineCooldown, MoveDir, Player},
+    input::lay_mine,
+    weapons::dig_crater,
+};
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_ggrs::prelude::*;
+
+/// Mines each player can lay per round
+pub const MINES_PER_ROUND: u32 = 3;
+pub const MINE_RADIUS: f32 = 1.0;
+/// Radius in tiles of the crater an exploding mine leaves
+pub const MINE_CRATER_RADIUS: u32 = 5;
+/// Frames before a mine goes off on contact, so it can't catch its owner while being laid
+pub const MINE_ARM_FRAMES: u32 = 30;
+/// Frames between laying two mines
+pub const MINE_COOLDOWN_FRAMES: u32 = 30;
+
+pub const COLOR_MINE: Color = Color::srgb(0.863, 0.078, 0.235);
+
+/// The render layer that shows the mines of the given player, which only their own follow camera
+/// and the overview look at
+pub fn mine_layer(owner_id: usize) -> usize {
+    1 + owner_id
+}
+
+/// Drops a mine behind the tank while the mine key is held, as long as there are mines left
+pub fn lay_mines(
+    mut commands: Commands,
+    inputs: Res<PlayerInputs<Config>>,
+    mut players: Query<(&Player, &Transform, &MoveDir, &mut MineCooldown)>,
+    mut game_stats: ResMut<GameStats>,
+) {
+    for (player, transform, move_dir, mut cooldown) in &mut players {
+        let stats = &mut game_stats[player.id];
+
+        if !lay_mine(inputs[player.id].0) || cooldown.0 > 0 || stats.mines == 0 {
+            continue;
+        }
+
+        let behind = transform.translation.xy() - move_dir.0 * (PLAYER_RADIUS + MINE_RADIUS);
+
+        commands
+            .spawn((
+                Mine {
+                    owner_id: player.id,
+                    age: 0,
+                },
+                Transform::from_translation(behind.extend(5.0)),
+            ))
+            .add_rollback();
+
+        stats.mines -= 1;
+        cooldown.0 = MINE_COOLDOWN_FRAMES;
+    }
+}
+
+/// Blows up armed mines that a tank touches, taking every tank on it and the terrain around it
+/// along
+pub fn trigger_mines(
+    mut commands: Commands,
+    mut mines: Query<(Entity, &mut Mine, &Transform)>,
+    players: Query<(Entity, &Player, &Transform), Without<Destroyed>>,
+    mut terrain: ResMut<Terrain>,
+) {
+    // tanks blown up by an earlier mine this frame, which another mine can't blow up again
+    let mut destroyed = Vec::new();
+
+    for (mine_entity, mut mine, mine_transform) in &mut mines {
+        mine.age += 1;
+
+        if mine.age <= MINE_ARM_FRAMES {
+            continue;
+        }
+
+        let mut exploded = false;
+
+        for (entity, player, player_transform) in &players {
+            let distance = Vec2::distance(
+                player_transform.translation.xy(),
+                mine_transform.translation.xy(),
+            );
+
+            if distance >= PLAYER_RADIUS + MINE_RADIUS || destroyed.contains(&entity) {
+                continue;
+            }
+
+            commands.entity(entity).insert(Destroyed);
+            destroyed.push(entity);
+            exploded = true;
+
+            info!(
+                "Player {} ran into a mine of Player {}!",
+                player.id, mine.owner_id
+            );
+        }
+
+        if exploded {
+            commands.entity(mine_entity).despawn();
+
+            let center = TilePos {
+                x: (mine_transform.translation.x + TERRAIN_WIDTH as f32 / 2.0).floor() as u32,
+                y: (mine_transform.translation.y + TERRAIN_HEIGHT as f32 / 2.0).floor() as u32,
+            };
+            dig_crater(&mut terrain, &center, MINE_CRATER_RADIUS, false);
+        }
+    }
+}
@@ -0,0 +1,452 @@
+use crate::{
+    Config, GameMode, GameState, GameStats, RoundOutcome,
+    args::Args,
+    components::{OnConnectionOverlay, OnNetworkStatsOverlay},
+};
+use bevy::prelude::*;
+use bevy_ggrs::{
+    LocalPlayers,
+    ggrs::{GgrsError, Message, NetworkStats, NonBlockingSocket},
+    prelude::*,
+};
+use bevy_matchbox::{matchbox_socket::WebRtcChannel, prelude::*};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// How long a peer may stay silent before GGRS drops them
+pub const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a peer has to be silent before GGRS reports the connection as interrupted
+pub const DISCONNECT_NOTIFY_DELAY: Duration = Duration::from_secs(1);
+/// How long the forfeit result stays on screen before returning to matchmaking
+const FORFEIT_SCREEN_DURATION: Duration = Duration::from_secs(5);
+
+/// The rate at which GGRS advances the simulation
+pub const GGRS_FPS: f32 = 60.0;
+/// Bounds for the input delay measured from the round-trip time
+pub const MIN_INPUT_DELAY: usize = 1;
+pub const MAX_INPUT_DELAY: usize = 8;
+/// Input delay for local sessions, where there's no connection to measure
+pub const DEFAULT_INPUT_DELAY: usize = 2;
+
+/// Picks an input delay that covers the time it takes for an input to reach the other player, so
+/// their inputs usually arrive before we need them and we rarely have to roll back
+pub fn input_delay_for(round_trip_time: Duration) -> usize {
+    let one_way_frames = (round_trip_time.as_secs_f32() / 2.0 * GGRS_FPS).ceil() as usize;
+    one_way_frames.clamp(MIN_INPUT_DELAY, MAX_INPUT_DELAY)
+}
+
+/// Whether the network statistics are shown, toggled in debug mode
+#[derive(Resource, Debug, Deref, DerefMut)]
+pub struct NetworkStatsVisible(pub bool);
+
+impl Default for NetworkStatsVisible {
+    fn default() -> Self {
+        NetworkStatsVisible(true)
+    }
+}
+
+/// Counts how often GGRS rolled back the world, per second
+#[derive(Resource, Debug)]
+pub struct RollbackCounter {
+    count: u32,
+    per_second: u32,
+    /// Bytes received from each peer in the last second
+    received_per_second: HashMap<PeerId, usize>,
+    timer: Timer,
+}
+
+impl Default for RollbackCounter {
+    fn default() -> Self {
+        RollbackCounter {
+            count: 0,
+            per_second: 0,
+            received_per_second: HashMap::new(),
+            timer: Timer::from_seconds(1.0, TimerMode::Repeating),
+        }
+    }
+}
+
+impl RollbackCounter {
+    /// Received traffic in the unit GGRS reports sent traffic in, which is really kilobytes
+    fn kbps_received(&self, peer: PeerId) -> usize {
+        self.received_per_second.get(&peer).copied().unwrap_or(0) / 1024
+    }
+}
+
+/// Bytes received from each peer on the GGRS channel, because GGRS only measures what it sends.
+/// They're counted the way GGRS counts what it sends, so both directions can be compared.
+#[derive(Resource, Clone, Default, Debug)]
+pub struct ReceivedBytes(Arc<Mutex<HashMap<PeerId, usize>>>);
+
+impl ReceivedBytes {
+    /// Size of the IP and UDP headers, which GGRS adds to every message it sends
+    const HEADER_SIZE: usize = 28;
+
+    /// The bytes received since the last call, by peer
+    fn take(&self) -> HashMap<PeerId, usize> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+/// The GGRS channel, counting the bytes that come in on it
+pub struct MeteredChannel {
+    channel: WebRtcChannel,
+    received: ReceivedBytes,
+}
+
+impl MeteredChannel {
+    pub fn new(channel: WebRtcChannel, received: &ReceivedBytes) -> Self {
+        MeteredChannel {
+            channel,
+            received: received.clone(),
+        }
+    }
+}
+
+impl NonBlockingSocket<PeerId> for MeteredChannel {
+    fn send_to(&mut self, msg: &Message, addr: &PeerId) {
+        self.channel.send_to(msg, addr);
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(PeerId, Message)> {
+        let messages = self.channel.receive_all_messages();
+        let mut received = self.received.0.lock().unwrap();
+
+        for (peer, message) in &messages {
+            *received.entry(*peer).or_default() +=
+                std::mem::size_of_val(message) + ReceivedBytes::HEADER_SIZE;
+        }
+
+        messages
+    }
+}
+
+/// How often GGRS found that our simulation diverged from the other player's
+#[derive(Resource, Default, Debug, Deref, DerefMut)]
+pub struct DesyncCount(u32);
+
+#[derive(Resource, Default, Debug)]
+pub enum ConnectionStatus {
+    #[default]
+    Connected,
+    /// The opponent stopped responding, counting down until GGRS gives up on them
+    Interrupted(Timer),
+    /// The opponent is gone, counting down until we return to matchmaking
+    Disconnected(Timer),
+}
+
+/// The peer an event is about, if any
+fn event_addr(event: &GgrsEvent<Config>) -> Option<PeerId> {
+    match event {
+        GgrsEvent::Synchronizing { addr, .. }
+        | GgrsEvent::Synchronized { addr }
+        | GgrsEvent::Disconnected { addr }
+        | GgrsEvent::NetworkInterrupted { addr, .. }
+        | GgrsEvent::NetworkResumed { addr } => Some(*addr),
+        _ => None,
+    }
+}
+
+pub fn handle_ggrs_events(
+    mut commands: Commands,
+    mut session: ResMut<Session<Config>>,
+    local_players: Res<LocalPlayers>,
+    mut status: ResMut<ConnectionStatus>,
+    mut desyncs: ResMut<DesyncCount>,
+    mut game_stats: ResMut<GameStats>,
+) {
+    let spectating = matches!(session.as_ref(), Session::Spectator(_));
+
+    let events: Vec<_> = match session.as_mut() {
+        Session::P2P(s) => {
+            let events: Vec<_> = s.events().collect();
+            events
+                .into_iter()
+                .filter(|event| {
+                    // spectators coming and going don't affect the match
+                    let is_spectator = event_addr(event).is_some_and(|addr| {
+                        s.handles_by_address(addr)
+                            .iter()
+                            .all(|handle| *handle >= s.num_players())
+                    });
+
+                    if is_spectator {
+                        info!("GGRS spectator event: {event:?}");
+                    }
+
+                    !is_spectator
+                })
+                .collect()
+        }
+        Session::Spectator(s) => s.events().collect(),
+        Session::SyncTest(_) => return,
+    };
+
+    for event in events {
+        match event {
+            GgrsEvent::NetworkInterrupted {
+                disconnect_timeout, ..
+            } => {
+                warn!("GGRS event: {event:?}");
+                let timeout = Duration::from_millis(disconnect_timeout as u64);
+                *status = ConnectionStatus::Interrupted(Timer::new(timeout, TimerMode::Once));
+            }
+            GgrsEvent::NetworkResumed { .. } => {
+                info!("GGRS event: {event:?}");
+                *status = ConnectionStatus::Connected;
+            }
+            GgrsEvent::Disconnected { .. } => {
+                warn!("GGRS event: {event:?}");
+                *status = ConnectionStatus::Disconnected(Timer::new(
+                    FORFEIT_SCREEN_DURATION,
+                    TimerMode::Once,
+                ));
+
+                // the remaining player wins by forfeit
+                if !spectating {
+                    for handle in &local_players.0 {
+                        game_stats[*handle].score += 1;
+                        game_stats.outcome = Some(RoundOutcome::Won(*handle as u8));
+                    }
+                }
+
+                // stop simulating, so the remaining player isn't playing against a ghost
+                commands.remove_resource::<Session<Config>>();
+            }
+            GgrsEvent::DesyncDetected {
+                local_checksum,
+                remote_checksum,
+                frame,
+                ..
+            } => {
+                **desyncs += 1;
+                error!(
+                    "Desync on frame {frame}. Local checksum: {local_checksum:X}, remote checksum: {remote_checksum:X}"
+                );
+            }
+            _ => info!("GGRS event: {event:?}"),
+        }
+    }
+}
+
+pub fn spawn_connection_overlay(mut commands: Commands) {
+    commands.spawn((
+        OnConnectionOverlay,
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        BackgroundColor(Color::BLACK.with_alpha(0.7)),
+        Visibility::Hidden,
+        children![(Text::new(""), TextLayout::new_with_justify(Justify::Center),)],
+    ));
+}
+
+pub fn update_connection_overlay(
+    time: Res<Time>,
+    game_mode: Option<Res<GameMode>>,
+    game_stats: Res<GameStats>,
+    mut status: ResMut<ConnectionStatus>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut overlays: Query<(&mut Visibility, &Children), With<OnConnectionOverlay>>,
+    mut texts: Query<&mut Text>,
+) {
+    let spectating = matches!(game_mode.as_deref(), Some(GameMode::Spectate(_)));
+
+    let message = match status.as_mut() {
+        ConnectionStatus::Connected => None,
+        ConnectionStatus::Interrupted(timer) => {
+            timer.tick(time.delta());
+            let waiting_for = if spectating {
+                "the players"
+            } else {
+                "the other player"
+            };
+            Some(format!(
+                "Connection interrupted\n\nWaiting for {waiting_for}... {}s",
+                timer.remaining().as_secs() + 1
+            ))
+        }
+        ConnectionStatus::Disconnected(timer) => {
+            timer.tick(time.delta());
+
+            if timer.just_finished() {
+                next_state.set(GameState::Matchmaking);
+            }
+
+            let result = if spectating {
+                "A player left the match"
+            } else {
+                "The other player left the match\n\nYou win!"
+            };
+            let scores = game_stats
+                .iter()
+                .map(|stats| stats.score.to_string())
+                .collect::<Vec<_>>()
+                .join(" - ");
+            Some(format!(
+                "{result}\n{scores}\n\nReturning to matchmaking in {}s",
+                timer.remaining().as_secs() + 1
+            ))
+        }
+    };
+
+    for (mut visibility, children) in &mut overlays {
+        *visibility = match message {
+            Some(_) => Visibility::Visible,
+            None => Visibility::Hidden,
+        };
+
+        for child in children {
+            if let (Ok(mut text), Some(message)) = (texts.get_mut(*child), &message) {
+                text.0.clone_from(message);
+            }
+        }
+    }
+}
+
+pub fn count_rollbacks(
+    session: Option<Res<Session<Config>>>,
+    mut counter: ResMut<RollbackCounter>,
+) {
+    if matches!(session.as_deref(), Some(Session::SyncTest(_))) {
+        return; // skip system: sync tests roll back every frame on purpose
+    }
+
+    counter.count += 1;
+}
+
+pub fn spawn_network_stats_overlay(mut commands: Commands) {
+    commands.spawn((
+        OnNetworkStatsOverlay,
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            left: Val::Px(10.0),
+            padding: UiRect::all(Val::Px(5.0)),
+            ..default()
+        },
+        BackgroundColor(Color::BLACK.with_alpha(0.7)),
+        Text::new(""),
+        TextFont::from_font_size(12.0),
+        Visibility::Hidden,
+    ));
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_network_stats_overlay(
+    time: Res<Time>,
+    args: Res<Args>,
+    visible: Res<NetworkStatsVisible>,
+    session: Option<Res<Session<Config>>>,
+    local_players: Res<LocalPlayers>,
+    received: Res<ReceivedBytes>,
+    mut counter: ResMut<RollbackCounter>,
+    mut overlays: Query<(&mut Text, &mut Visibility), With<OnNetworkStatsOverlay>>,
+) {
+    if counter.timer.tick(time.delta()).just_finished() {
+        counter.per_second = counter.count;
+        counter.count = 0;
+        counter.received_per_second = received.take();
+    }
+
+    let lines = match session.as_deref() {
+        Some(Session::P2P(session)) => {
+            let mut lines = vec![
+                format!("Local players: {:?}", local_players.0),
+                format!("Frames ahead: {}", session.frames_ahead()),
+                format!("Rollbacks: {}/s", counter.per_second),
+            ];
+
+            let kbps_received = |handle| {
+                counter
+                    .received_per_second
+                    .keys()
+                    .find(|peer| session.handles_by_address(**peer).contains(&handle))
+                    .map_or(0, |peer| counter.kbps_received(*peer))
+            };
+
+            for handle in session.remote_player_handles() {
+                lines.push(format!("\nPlayer {handle}"));
+                push_network_stats(
+                    &mut lines,
+                    session.network_stats(handle),
+                    kbps_received(handle),
+                );
+            }
+
+            for handle in session.spectator_handles() {
+                lines.push(format!("\nSpectator {handle}"));
+                push_network_stats(
+                    &mut lines,
+                    session.network_stats(handle),
+                    kbps_received(handle),
+                );
+            }
+
+            lines
+        }
+        Some(Session::Spectator(session)) => {
+            let mut lines = vec![
+                String::from("Spectating"),
+                format!("Frames behind host: {}", session.frames_behind_host()),
+                String::from("\nHost"),
+            ];
+            let kbps_received = counter.received_per_second.values().sum::<usize>() / 1024;
+            push_network_stats(&mut lines, session.network_stats(), kbps_received);
+            lines
+        }
+        _ => {
+            for (_, mut visibility) in &mut overlays {
+                *visibility = Visibility::Hidden;
+            }
+            return; // only networked sessions have network statistics
+        }
+    };
+
+    for (mut text, mut visibility) in &mut overlays {
+        *visibility = if args.debug && **visible {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+        text.0 = lines.join("\n");
+    }
+}
+
+fn push_network_stats(
+    lines: &mut Vec<String>,
+    stats: Result<NetworkStats, GgrsError>,
+    kbps_received: usize,
+) {
+    match stats {
+        Ok(stats) => {
+            lines.push(format!("  Ping: {} ms", stats.ping));
+            lines.push(format!(
+                "  Frames behind: {} local, {} remote",
+                stats.local_frames_behind, stats.remote_frames_behind
+            ));
+            lines.push(format!("  Send queue: {}", stats.send_queue_len));
+            lines.push(format!("  Sent: {} kbps", stats.kbps_sent));
+            lines.push(format!("  Received: {kbps_received} kbps"));
+        }
+        Err(e) => lines.push(format!("  {e}")),
+    }
+}
+
+/// Tears down the network session, so we can start cleanly on the next match
+pub fn teardown_session(mut commands: Commands) {
+    commands.remove_resource::<Session<Config>>();
+    commands.close_socket();
+    commands.insert_resource(ConnectionStatus::default());
+    commands.insert_resource(RollbackCounter::default());
+    commands.insert_resource(ReceivedBytes::default());
+    commands.insert_resource(DesyncCount::default());
+}
@@ -0,0 +1,110 @@
+use crate::{
+    COLOR_ENERGY, COLOR_GREEN, COLOR_SHIELD, GameStats, MAX_ENERGY, MAX_SHIELD, PLAYER_RADIUS,
+    PlayerStats, TERRAIN_HEIGHT, TERRAIN_WIDTH, TerrainType,
+    components::{Player, PowerUp},
+    weapons::WeaponKind,
+};
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_ggrs::prelude::*;
+use fastrand::Rng;
+
+/// How many power-ups are buried in the terrain at the start of a match
+pub const POWER_UP_COUNT: usize = 40;
+pub const POWER_UP_RADIUS: f32 = 1.5;
+/// Energy restored by an energy cell
+pub const ENERGY_CELL: u32 = 40;
+/// Frames a speed burst lasts
+pub const SPEED_BURST_FRAMES: u32 = 240;
+/// How much faster a tank drives during a speed burst
+pub const SPEED_BURST_FACTOR: f32 = 1.5;
+/// Shots that come with a weapon power-up
+pub const WEAPON_AMMO: u32 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerUpKind {
+    Energy,
+    Shield,
+    SpeedBurst,
+    Weapon(WeaponKind),
+}
+
+impl PowerUpKind {
+    fn random(rng: &mut Rng) -> Self {
+        match rng.u8(0..4) {
+            0 => PowerUpKind::Energy,
+            1 => PowerUpKind::Shield,
+            2 => PowerUpKind::SpeedBurst,
+            // any weapon but the cannon, which every tank already has
+            _ => PowerUpKind::Weapon(WeaponKind::ALL[rng.usize(1..WeaponKind::ALL.len())]),
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            PowerUpKind::Energy => COLOR_ENERGY,
+            PowerUpKind::Shield => COLOR_SHIELD,
+            PowerUpKind::SpeedBurst => COLOR_GREEN,
+            PowerUpKind::Weapon(_) => Color::WHITE,
+        }
+    }
+
+    fn apply(self, stats: &mut PlayerStats) {
+        match self {
+            PowerUpKind::Energy => stats.energy = (stats.energy + ENERGY_CELL).min(MAX_ENERGY),
+            PowerUpKind::Shield => stats.shield = (stats.shield + 1).min(MAX_SHIELD),
+            PowerUpKind::SpeedBurst => stats.speed_burst = SPEED_BURST_FRAMES,
+            PowerUpKind::Weapon(kind) => stats.ammo[kind as usize] += WEAPON_AMMO,
+        }
+    }
+}
+
+/// Buries power-ups in random dirt tiles, drawn below the terrain so digging uncovers them
+pub fn spawn_power_ups(commands: &mut Commands, terrain: &[(TilePos, TerrainType)], rng: &mut Rng) {
+    let mut placed = 0;
+
+    while placed < POWER_UP_COUNT {
+        let (pos, terrain_type) = terrain[rng.usize(..terrain.len())];
+
+        if let TerrainType::Rock = terrain_type {
+            continue;
+        }
+
+        let kind = PowerUpKind::random(rng);
+        let translation = Vec3::new(
+            pos.x as f32 - TERRAIN_WIDTH as f32 / 2.0 + 0.5,
+            pos.y as f32 - TERRAIN_HEIGHT as f32 / 2.0 + 0.5,
+            -1.0,
+        );
+
+        commands
+            .spawn((PowerUp { kind }, Transform::from_translation(translation)))
+            .add_rollback();
+
+        placed += 1;
+    }
+}
+
+pub fn collect_power_ups(
+    mut commands: Commands,
+    players: Query<(&Player, &Transform)>,
+    power_ups: Query<(Entity, &PowerUp, &Transform)>,
+    mut game_stats: ResMut<GameStats>,
+) {
+    for (player, player_transform) in &players {
+        for (entity, power_up, transform) in &power_ups {
+            let distance = Vec2::distance(
+                player_transform.translation.xy(),
+                transform.translation.xy(),
+            );
+
+            if distance < PLAYER_RADIUS + POWER_UP_RADIUS {
+                // both tanks get it when they reach it at the same time
+                commands.entity(entity).try_despawn();
+                power_up.kind.apply(&mut game_stats[player.id]);
+
+                info!("Player {} picked up {:?}", player.id, power_up.kind);
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{args::Args, generate_terrain};
+use bevy::prelude::*;
+use fastrand::Rng;
+use std::hash::Hasher;
+
+/// Identifier of this build, which has to match exactly between peers. The build script derives it
+/// from the sources, so any change to the code gives a different one.
+const VERSION: &str = match option_env!("BUILD_ID") {
+    Some(build_id) => build_id,
+    None => env!("CARGO_PKG_VERSION"),
+};
+
+const MAP_CHECK_SEED: u64 = 42;
+
+/// Rules that can be changed per match, which all peers have to agree on
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MatchRules {
+    /// Whether bullets can hit the tank that fired them, once they've flown for a while
+    pub self_hits: bool,
+    /// Whether both players score a point when their tanks are destroyed in the same frame
+    pub draw_points: bool,
+}
+
+impl MatchRules {
+    pub fn from_args(args: &Args) -> Self {
+        MatchRules {
+            self_hits: args.self_hits,
+            draw_points: args.draw_points,
+        }
+    }
+}
+
+/// What peers compare before playing together, because any difference would make their
+/// simulations diverge
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct Handshake {
+    pub version: String,
+    pub rules_hash: u64,
+    pub map_hash: u64,
+}
+
+impl Handshake {
+    pub fn local(rules: &MatchRules) -> Self {
+        Handshake {
+            version: String::from(VERSION),
+            rules_hash: rules_hash(rules),
+            map_hash: map_hash(),
+        }
+    }
+
+    /// Explains why we can't play with a peer that sent the given handshake, if we can't
+    pub fn mismatch(&self, remote: &Handshake) -> Option<String> {
+        if self.version != remote.version {
+            Some(format!(
+                "they run version {}, you run version {}",
+                remote.version, self.version
+            ))
+        } else if self.rules_hash != remote.rules_hash {
+            Some(String::from("their game rules differ from yours"))
+        } else if self.map_hash != remote.map_hash {
+            Some(String::from("their map differs from yours"))
+        } else {
+            None
+        }
+    }
+}
+
+/// Hashes the rules chosen for this match. The gameplay constants are part of the code, so the
+/// build identifier already covers them.
+fn rules_hash(rules: &MatchRules) -> u64 {
+    // taken apart, so a new rule doesn't compile until it's hashed too
+    let MatchRules {
+        self_hits,
+        draw_points,
+    } = *rules;
+
+    let mut hasher = Fnv1a::default();
+    hasher.write(&[self_hits as u8]);
+    hasher.write(&[draw_points as u8]);
+    hasher.finish()
+}
+
+/// Takes the rules for the next match from the settings, and prepares to tell peers about them
+pub fn apply_match_rules(mut commands: Commands, args: Res<Args>) {
+    let rules = MatchRules::from_args(&args);
+
+    commands.insert_resource(Handshake::local(&rules));
+    commands.insert_resource(rules);
+}
+
+/// Checks the map generator by hashing the map it makes from a fixed seed, since the actual map
+/// comes from the session seed that isn't known until the match starts
+fn map_hash() -> u64 {
+    let mut hasher = Fnv1a::default();
+
+    for (tile_pos, terrain_type) in generate_terrain(&mut Rng::with_seed(MAP_CHECK_SEED)) {
+        hasher.write(&tile_pos.x.to_le_bytes());
+        hasher.write(&tile_pos.y.to_le_bytes());
+        hasher.write(&[terrain_type as u8]);
+    }
+
+    hasher.finish()
+}
+
+/// FNV-1a, because unlike the standard library's hasher, it gives the same result on every
+/// platform and compiler version. Sizes are hashed as 64 bits, so derived hashes match between
+/// native and web builds too.
+pub struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.write(&(value as u64).to_le_bytes());
+    }
+
+    fn write_isize(&mut self, value: isize) {
+        self.write(&(value as i64).to_le_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{
+    COLOR_ENERGY, COLOR_UI, Config, GameStats, TERRAIN_HEIGHT, TERRAIN_WIDTH, Terrain, TerrainType,
+    components::{Player, Radar},
+    input::sonar,
+};
+use bevy::{
+    asset::RenderAssetUsages,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use bevy_ecs_tilemap::prelude::*;
+use bevy_ggrs::prelude::*;
+
+/// Tiles along each side of a radar pixel
+const RADAR_SCALE: u32 = 4;
+const RADAR_WIDTH: u32 = TERRAIN_WIDTH.div_ceil(RADAR_SCALE);
+const RADAR_HEIGHT: u32 = TERRAIN_HEIGHT.div_ceil(RADAR_SCALE);
+/// Distance within which the opponent shows up on the radar without a sonar ping
+const RADAR_RANGE: f32 = 60.0;
+/// Energy a sonar ping costs
+const SONAR_ENERGY: u32 = 20;
+/// Frames the opponent shows up on the radar after a sonar ping
+const SONAR_FRAMES: u32 = 120;
+
+const COLOR_RADAR_BLIP: Color = Color::srgb(0.863, 0.078, 0.235);
+
+/// The radar in the corner of a player's viewport, drawn into a small image of its own
+pub fn radar_panel(player_id: usize, images: &mut Assets<Image>) -> impl Bundle {
+    let image = Image::new_fill(
+        Extent3d {
+            width: RADAR_WIDTH,
+            height: RADAR_HEIGHT,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+
+    (
+        Radar {
+            player_id,
+            explored: vec![false; (RADAR_WIDTH * RADAR_HEIGHT) as usize],
+        },
+        ImageNode::new(images.add(image)),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            right: Val::Px(10.0),
+            width: Val::Px((RADAR_WIDTH * 2) as f32),
+            height: Val::Px((RADAR_HEIGHT * 2) as f32),
+            border: UiRect::all(Val::Px(1.0)),
+            ..default()
+        },
+        BorderColor::all(COLOR_UI),
+    )
+}
+
+/// Sends out a sonar ping while the sonar key is held, which shows the opponent on the radar for a
+/// while, as long as there's energy for it
+pub fn ping_sonar(
+    inputs: Res<PlayerInputs<Config>>,
+    players: Query<&Player>,
+    mut game_stats: ResMut<GameStats>,
+) {
+    for player in &players {
+        let stats = &mut game_stats[player.id];
+
+        if stats.sonar > 0 {
+            stats.sonar -= 1;
+        } else if sonar(inputs[player.id].0) && stats.energy >= SONAR_ENERGY {
+            stats.energy -= SONAR_ENERGY;
+            stats.sonar = SONAR_FRAMES;
+        }
+    }
+}
+
+/// The radar pixel that covers the given position in the world
+fn radar_cell(pos: Vec2) -> UVec2 {
+    let x = (pos.x + TERRAIN_WIDTH as f32 / 2.0).max(0.0) as u32 / RADAR_SCALE;
+    let y = (pos.y + TERRAIN_HEIGHT as f32 / 2.0).max(0.0) as u32 / RADAR_SCALE;
+
+    UVec2::new(x, y).min(UVec2::new(RADAR_WIDTH - 1, RADAR_HEIGHT - 1))
+}
+
+/// Whether any of the tiles under a radar pixel has been dug out. The pixels along the top and right
+/// edges cover fewer tiles, because the terrain doesn't divide evenly into them.
+fn is_dug(terrain: &Terrain, cell: UVec2) -> bool {
+    let start = cell * RADAR_SCALE;
+    let end = (start + RADAR_SCALE).min(UVec2::new(TERRAIN_WIDTH, TERRAIN_HEIGHT));
+
+    (start.x..end.x).any(|x| {
+        (start.y..end.y).any(|y| terrain.get(&TilePos { x, y }) == Some(TerrainType::Empty))
+    })
+}
+
+fn set_cell(image: &mut Image, cell: UVec2, color: Color) {
+    // the image starts at the top, the terrain at the bottom
+    let _ = image.set_color_at(cell.x, RADAR_HEIGHT - 1 - cell.y, color);
+}
+
+/// Draws the tunnels the player has been through, their base and their tank onto their radar, and
+/// the opponent while they're close or after a sonar ping
+pub fn update_radars(
+    terrain: Res<Terrain>,
+    game_stats: Res<GameStats>,
+    players: Query<(&Player, &Transform)>,
+    mut radars: Query<(&mut Radar, &ImageNode)>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for (mut radar, image_node) in &mut radars {
+        let Some(image) = images.get_mut(&image_node.image) else {
+            continue;
+        };
+
+        let own_position = players
+            .iter()
+            .find(|(player, _)| player.id == radar.player_id)
+            .map(|(_, transform)| transform.translation.xy());
+
+        if let Some(position) = own_position {
+            // the tank digs a bit around itself, which can spill over into the next pixels
+            let center = radar_cell(position).as_ivec2();
+
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    let cell = center + IVec2::new(dx, dy);
+
+                    if cell.x >= 0
+                        && cell.y >= 0
+                        && cell.x < RADAR_WIDTH as i32
+                        && cell.y < RADAR_HEIGHT as i32
+                    {
+                        radar.explored[(cell.x as u32 * RADAR_HEIGHT + cell.y as u32) as usize] =
+                            true;
+                    }
+                }
+            }
+        }
+
+        for x in 0..RADAR_WIDTH {
+            for y in 0..RADAR_HEIGHT {
+                let cell = UVec2::new(x, y);
+                let color =
+                    if radar.explored[(x * RADAR_HEIGHT + y) as usize] && is_dug(&terrain, cell) {
+                        COLOR_UI
+                    } else {
+                        Color::BLACK
+                    };
+
+                set_cell(image, cell, color);
+            }
+        }
+
+        let stats = &game_stats[radar.player_id];
+        let base = stats.base / RADAR_SCALE;
+        set_cell(image, base, COLOR_ENERGY);
+
+        let Some(own_position) = own_position else {
+            continue; // the tank was destroyed, so there's nobody to look around
+        };
+
+        set_cell(image, radar_cell(own_position), Color::WHITE);
+
+        for (player, transform) in &players {
+            let position = transform.translation.xy();
+
+            if player.id != radar.player_id
+                && (stats.sonar > 0 || position.distance(own_position) < RADAR_RANGE)
+            {
+                set_cell(image, radar_cell(position), COLOR_RADAR_BLIP);
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{
+    components::{Bullet, Player, Smoothing},
+    network::GGRS_FPS,
+};
+use bevy::prelude::*;
+use bevy_ggrs::RollbackFrameCount;
+use std::time::Duration;
+
+/// How much of a rollback correction is left after each frame of the simulation, so it fades out
+/// over a handful of frames instead of snapping
+const CORRECTION_DECAY: f32 = 0.6;
+/// Corrections smaller than this are not worth drawing
+const CORRECTION_EPSILON: f32 = 0.01;
+
+/// Keeps track of the simulation frames, to know how far along the next one we are
+#[derive(Resource, Debug, Default)]
+pub struct SimulationClock {
+    /// The latest frame that was drawn
+    frame: i32,
+    since_frame: Duration,
+    /// How far along the latest frame it was drawn
+    alpha: f32,
+    rolled_back: bool,
+}
+
+pub fn note_rollback(mut clock: ResMut<SimulationClock>) {
+    clock.rolled_back = true;
+}
+
+/// Remembers the last two simulated transforms, also of the frames that are simulated again after
+/// a rollback, so the drawing always goes between frames as they ended up
+pub fn record_frame(
+    frame: Res<RollbackFrameCount>,
+    clock: Res<SimulationClock>,
+    mut query: Query<(&Transform, &mut Smoothing)>,
+) {
+    for (transform, mut smoothing) in &mut query {
+        smoothing.previous = smoothing.current;
+        smoothing.current = *transform;
+
+        if clock.rolled_back && frame.0 == clock.frame {
+            // the frame that was drawn last, as it turned out after the rollback
+            smoothing.resimulated = Some(
+                smoothing
+                    .previous
+                    .translation
+                    .lerp(smoothing.current.translation, clock.alpha),
+            );
+        }
+    }
+}
+
+/// Draws tanks and bullets in between the last two simulation frames, and fades out the jumps
+/// that rollbacks make
+#[allow(clippy::type_complexity)]
+pub fn interpolate(
+    mut commands: Commands,
+    time: Res<Time>,
+    frame: Res<RollbackFrameCount>,
+    mut clock: ResMut<SimulationClock>,
+    mut query: Query<
+        (Entity, &Transform, Option<&mut Smoothing>),
+        Or<(With<Player>, With<Bullet>)>,
+    >,
+) {
+    let advanced = frame.0 != clock.frame;
+
+    if advanced {
+        clock.frame = frame.0;
+        clock.since_frame = Duration::ZERO;
+    } else {
+        clock.since_frame += time.delta();
+    }
+
+    let alpha = (clock.since_frame.as_secs_f32() * GGRS_FPS).min(1.0);
+
+    for (entity, transform, smoothing) in &mut query {
+        let Some(mut smoothing) = smoothing else {
+            // just spawned, so there's nothing to come from
+            commands.entity(entity).insert(Smoothing::new(*transform));
+            continue;
+        };
+
+        // where the rollback moved it, compared to where it was drawn last time, before that fades
+        let jump = smoothing
+            .resimulated
+            .take()
+            .map(|resimulated| smoothing.visual.translation - smoothing.correction - resimulated);
+
+        if advanced {
+            smoothing.correction *= CORRECTION_DECAY;
+            if smoothing.correction.length() < CORRECTION_EPSILON {
+                smoothing.correction = Vec3::ZERO;
+            }
+        }
+
+        // keep drawing it where it was, but only the ones the rollback actually moved
+        if let Some(jump) = jump
+            && jump.length() >= CORRECTION_EPSILON
+        {
+            smoothing.correction += jump;
+        }
+
+        let mut visual = Transform {
+            translation: smoothing
+                .previous
+                .translation
+                .lerp(smoothing.current.translation, alpha),
+            rotation: smoothing
+                .previous
+                .rotation
+                .slerp(smoothing.current.rotation, alpha),
+            scale: smoothing.current.scale,
+        };
+        visual.translation += smoothing.correction;
+        smoothing.visual = visual;
+    }
+
+    clock.alpha = alpha;
+    clock.rolled_back = false;
+}
+
+/// Draws with the smoothed transforms instead of the simulated ones, after Bevy has worked out
+/// where everything is
+pub fn apply_visual_transforms(mut query: Query<(&Smoothing, &mut GlobalTransform)>) {
+    for (smoothing, mut global_transform) in &mut query {
+        *global_transform = smoothing.visual.into();
+    }
+}
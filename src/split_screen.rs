@@ -0,0 +1,105 @@
+use crate::{CameraMode, components::CameraPosition};
+use bevy::{camera::Viewport, prelude::*};
+
+/// Logical pixels between the viewports and around the edge of the window
+const VIEWPORT_GAP: f32 = 10.0;
+
+/// How the viewports of the followed tanks share the window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SplitLayout {
+    /// One viewport that fills the whole window
+    Single,
+    /// Next to each other, for wide windows
+    SideBySide,
+    /// On top of each other, for tall windows
+    Stacked,
+    /// Two columns, for more than two players
+    Grid,
+}
+
+impl SplitLayout {
+    /// Picks the layout that gives each viewport the most room to show the square around its tank
+    fn pick(count: u32, window_size: UVec2, gap: u32) -> Self {
+        if count <= 1 {
+            return SplitLayout::Single;
+        }
+
+        // on a tie the last one wins, so a square window is split side by side
+        let candidates: &[SplitLayout] = if count > 2 {
+            &[
+                SplitLayout::Stacked,
+                SplitLayout::SideBySide,
+                SplitLayout::Grid,
+            ]
+        } else {
+            &[SplitLayout::Stacked, SplitLayout::SideBySide]
+        };
+
+        candidates
+            .iter()
+            .copied()
+            .max_by_key(|layout| layout.cell_size(count, window_size, gap).min_element())
+            .unwrap_or(SplitLayout::SideBySide)
+    }
+
+    /// Columns and rows of viewports
+    fn grid(self, count: u32) -> UVec2 {
+        match self {
+            SplitLayout::Single => UVec2::ONE,
+            SplitLayout::SideBySide => UVec2::new(count, 1),
+            SplitLayout::Stacked => UVec2::new(1, count),
+            SplitLayout::Grid => UVec2::new(2, count.div_ceil(2)),
+        }
+    }
+
+    fn cell_size(self, count: u32, window_size: UVec2, gap: u32) -> UVec2 {
+        let grid = self.grid(count);
+        let free = window_size.saturating_sub(gap * (grid + UVec2::ONE));
+
+        (free / grid).max(UVec2::ONE)
+    }
+
+    /// The viewport in the given slot, counting along the rows from the top left
+    fn viewport(self, slot: u32, count: u32, window_size: UVec2, gap: u32) -> Viewport {
+        let grid = self.grid(count);
+        let size = self.cell_size(count, window_size, gap);
+        let cell = UVec2::new(slot % grid.x, slot / grid.x);
+
+        Viewport {
+            physical_position: UVec2::splat(gap) + cell * (size + UVec2::splat(gap)),
+            physical_size: size,
+            ..default()
+        }
+    }
+}
+
+/// Lays out the viewports of the followed tanks for the shape of the window, so they fit on any
+/// window size and pixel density
+pub fn set_camera_viewports(
+    windows: Query<&Window>,
+    camera_mode: Res<CameraMode>,
+    mut query: Query<(&CameraPosition, &mut Camera)>,
+) {
+    let Ok(window) = windows.single() else {
+        return; // skip system: there's no window to draw in
+    };
+
+    let mut shown: Vec<_> = query
+        .iter_mut()
+        .filter(|(position, _)| camera_mode.follows(position.pos.x as usize))
+        .collect();
+    shown.sort_by_key(|(position, _)| position.pos.x);
+
+    let count = shown.len() as u32;
+    let window_size = window.physical_size();
+    let gap = (VIEWPORT_GAP * window.scale_factor()) as u32;
+    let layout = SplitLayout::pick(count, window_size, gap);
+
+    for (slot, (_, camera)) in shown.iter_mut().enumerate() {
+        camera.viewport = match layout {
+            // online, or when following a single tank, the camera gets the whole window
+            SplitLayout::Single => None,
+            _ => Some(layout.viewport(slot as u32, count, window_size, gap)),
+        };
+    }
+}
@@ -0,0 +1,56 @@
+use crate::{
+    ImageAssets,
+    components::{Bullet, Mine, Player, PowerUp},
+    mines::{COLOR_MINE, MINE_RADIUS, mine_layer},
+    power_ups::POWER_UP_RADIUS,
+};
+use bevy::{camera::visibility::RenderLayers, prelude::*};
+
+const TANK_SIZE: Vec2 = Vec2::new(5.0, 7.0);
+
+/// Gives everything the simulation spawns a sprite to draw it with. Sprites aren't rolled back, so
+/// this also covers whatever a rollback brings back.
+#[allow(clippy::type_complexity)]
+pub fn attach_sprites(
+    mut commands: Commands,
+    images: Res<ImageAssets>,
+    players: Query<(Entity, &Player), Without<Sprite>>,
+    bullets: Query<(Entity, &Bullet), Without<Sprite>>,
+    mines: Query<(Entity, &Mine), Without<Sprite>>,
+    power_ups: Query<(Entity, &PowerUp), Without<Sprite>>,
+) {
+    for (entity, player) in &players {
+        let image = match player.id {
+            0 => images.tank_blue.clone(),
+            _ => images.tank_green.clone(),
+        };
+
+        commands.entity(entity).insert(Sprite {
+            image,
+            custom_size: Some(TANK_SIZE),
+            ..default()
+        });
+    }
+
+    for (entity, bullet) in &bullets {
+        commands.entity(entity).insert(Sprite {
+            image: images.bullet.clone(),
+            custom_size: Some(Vec2::new(2.0, 4.0) * bullet.weapon.stats().radius),
+            ..default()
+        });
+    }
+
+    for (entity, mine) in &mines {
+        commands.entity(entity).insert((
+            Sprite::from_color(COLOR_MINE, Vec2::splat(MINE_RADIUS * 2.0)),
+            RenderLayers::layer(mine_layer(mine.owner_id)),
+        ));
+    }
+
+    for (entity, power_up) in &power_ups {
+        commands.entity(entity).insert(Sprite::from_color(
+            power_up.kind.color(),
+            Vec2::splat(POWER_UP_RADIUS * 2.0),
+        ));
+    }
+}
@@ -0,0 +1,248 @@
+use crate::{
+    BULLET_RADIUS, Config, GameStats, PlayerStats, SPEED_BULLET, TERRAIN_HEIGHT, TERRAIN_WIDTH,
+    Terrain, TerrainType,
+    components::{Bullet, MoveDir, Player, Weapon},
+    get_neighbors_in_radius,
+    input::switch_weapon,
+};
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_ggrs::prelude::*;
+
+/// How many of a player's bullets can be in flight at once
+pub const MAX_LIVE_BULLETS: usize = 6;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WeaponKind {
+    #[default]
+    Standard,
+    HeavyShell,
+    Spread,
+    Drill,
+}
+
+impl WeaponKind {
+    pub const ALL: [WeaponKind; 4] = [
+        WeaponKind::Standard,
+        WeaponKind::HeavyShell,
+        WeaponKind::Spread,
+        WeaponKind::Drill,
+    ];
+
+    pub fn stats(self) -> &'static WeaponStats {
+        &WEAPONS[self as usize]
+    }
+
+    /// The next weapon the player has ammo for, which is the cannon when there's no other
+    fn next(self, stats: &PlayerStats) -> Self {
+        let count = WeaponKind::ALL.len();
+
+        (1..count)
+            .map(|offset| WeaponKind::ALL[(self as usize + offset) % count])
+            .find(|kind| stats.has_ammo(*kind))
+            .unwrap_or(WeaponKind::Standard)
+    }
+}
+
+/// How the bullets of a weapon fly and what they do to the terrain they hit
+#[derive(Debug)]
+pub struct WeaponStats {
+    pub name: &'static str,
+    /// Tiles per second
+    pub speed: f32,
+    pub radius: f32,
+    /// Frames between shots, at 60 frames per second
+    pub cooldown: u32,
+    /// Energy each shot costs
+    pub energy: u32,
+    /// Bullets fired per shot, fanned out sideways
+    pub projectiles: u32,
+    /// Sideways speed between neighbouring bullets of one shot, relative to their forward speed
+    pub spread: f32,
+    /// Radius in tiles of the crater a bullet digs where it stops
+    pub crater_radius: u32,
+    /// How many solid tiles a bullet bores through before it stops
+    pub penetration: u32,
+    /// Whether the bullet breaks rock, which stops all other bullets without a dent
+    pub cracks_rock: bool,
+}
+
+/// Stats of every weapon, in the order of `WeaponKind`
+pub const WEAPONS: [WeaponStats; 4] = [
+    WeaponStats {
+        name: "Cannon",
+        speed: SPEED_BULLET,
+        radius: BULLET_RADIUS,
+        cooldown: 12,
+        energy: 2,
+        projectiles: 1,
+        spread: 0.0,
+        crater_radius: 1,
+        penetration: 0,
+        cracks_rock: false,
+    },
+    WeaponStats {
+        name: "Heavy shell",
+        speed: 56.0,
+        radius: 1.0,
+        cooldown: 45,
+        energy: 12,
+        projectiles: 1,
+        spread: 0.0,
+        crater_radius: 6,
+        penetration: 0,
+        cracks_rock: false,
+    },
+    WeaponStats {
+        name: "Spread shot",
+        speed: SPEED_BULLET,
+        radius: BULLET_RADIUS,
+        cooldown: 30,
+        energy: 6,
+        projectiles: 3,
+        spread: 0.15,
+        crater_radius: 1,
+        penetration: 0,
+        cracks_rock: false,
+    },
+    WeaponStats {
+        name: "Drill",
+        speed: 70.0,
+        radius: BULLET_RADIUS,
+        cooldown: 40,
+        energy: 10,
+        projectiles: 1,
+        spread: 0.0,
+        crater_radius: 1,
+        penetration: 24,
+        cracks_rock: true,
+    },
+];
+
+/// Switches to the next weapon each time the switch key is pressed
+pub fn switch_weapons(
+    inputs: Res<PlayerInputs<Config>>,
+    game_stats: Res<GameStats>,
+    mut players: Query<(&Player, &mut Weapon)>,
+) {
+    for (player, mut weapon) in &mut players {
+        let (input, _) = inputs[player.id];
+
+        if !switch_weapon(input) {
+            weapon.switch_ready = true;
+        } else if weapon.switch_ready {
+            weapon.kind = weapon.kind.next(&game_stats[player.id]);
+            weapon.switch_ready = false;
+        }
+    }
+}
+
+/// The directions of the bullets of one shot in the given direction
+pub fn shot_directions(stats: &WeaponStats, direction: Vec2) -> impl Iterator<Item = Vec2> {
+    let sideways = direction.perp() * stats.spread;
+    let middle = (stats.projectiles - 1) as f32 / 2.0;
+
+    // no trigonometry, so every platform fans out the bullets exactly the same
+    (0..stats.projectiles)
+        .map(move |i| (direction + sideways * (i as f32 - middle)).normalize_or(direction))
+}
+
+/// Destroys bullets that run into a bullet of another player, both of them
+pub fn cancel_bullets(
+    mut commands: Commands,
+    bullets: Query<(Entity, &Bullet, &Transform, &MoveDir)>,
+    time: Res<Time>,
+) {
+    for [
+        (entity_a, a, transform_a, dir_a),
+        (entity_b, b, transform_b, dir_b),
+    ] in bullets.iter_combinations()
+    {
+        if a.owner_id == b.owner_id {
+            continue; // the bullets of a spread shot start out on top of each other
+        }
+
+        let (stats_a, stats_b) = (a.weapon.stats(), b.weapon.stats());
+        let offset = transform_a.translation.xy() - transform_b.translation.xy();
+        let step = (dir_a.0 * stats_a.speed - dir_b.0 * stats_b.speed) * time.delta_secs();
+
+        // a bullet cancels every bullet it runs into, so the order of the pairs doesn't matter
+        if closest_approach(offset, step) < stats_a.radius + stats_b.radius {
+            commands.entity(entity_a).try_despawn();
+            commands.entity(entity_b).try_despawn();
+        }
+    }
+}
+
+/// How close two bullets came during the last frame, given how far apart they are now and how
+/// far they moved relative to each other, so fast bullets can't skip past each other
+fn closest_approach(offset: Vec2, step: Vec2) -> f32 {
+    let length_squared = step.length_squared();
+
+    if length_squared == 0.0 {
+        return offset.length();
+    }
+
+    let t = (-offset.dot(step) / length_squared).clamp(-1.0, 0.0);
+    (offset + step * t).length()
+}
+
+/// Stops bullets at the terrain they hit, digging craters and boring through it on the way
+pub fn hit_terrain(
+    mut commands: Commands,
+    mut bullets: Query<(Entity, &mut Bullet, &Transform)>,
+    mut terrain: ResMut<Terrain>,
+) {
+    for (entity, mut bullet, transform) in &mut bullets {
+        let x = (transform.translation.x + TERRAIN_WIDTH as f32 / 2.0).floor();
+        let y = (transform.translation.y + TERRAIN_HEIGHT as f32 / 2.0).floor();
+
+        if x < 0.0 || y < 0.0 || x >= TERRAIN_WIDTH as f32 || y >= TERRAIN_HEIGHT as f32 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let pos = TilePos {
+            x: x as u32,
+            y: y as u32,
+        };
+
+        let Some(terrain_type) = terrain.get(&pos) else {
+            continue;
+        };
+
+        if terrain_type == TerrainType::Empty {
+            continue; // flying through a tunnel
+        }
+
+        let stats = bullet.weapon.stats();
+
+        if terrain_type == TerrainType::Rock && !stats.cracks_rock {
+            commands.entity(entity).despawn();
+        } else if bullet.penetration > 0 {
+            terrain.dig(&pos);
+            bullet.penetration -= 1;
+        } else {
+            dig_crater(&mut terrain, &pos, stats.crater_radius, stats.cracks_rock);
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Digs out a round hole in the terrain, leaving the rock unless it cracks rock
+pub fn dig_crater(terrain: &mut Terrain, center: &TilePos, radius: u32, cracks_rock: bool) {
+    let radius_squared = (radius * radius) as i32;
+
+    for pos in get_neighbors_in_radius(center, radius) {
+        let dx = pos.x as i32 - center.x as i32;
+        let dy = pos.y as i32 - center.y as i32;
+
+        if dx * dx + dy * dy > radius_squared {
+            continue; // round off the corners
+        }
+
+        if cracks_rock || terrain.get(&pos) != Some(TerrainType::Rock) {
+            terrain.dig(&pos);
+        }
+    }
+}
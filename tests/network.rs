@@ -0,0 +1,111 @@
+//! Plays a quick match between two headless instances over a local signaling server, and checks
+//! that both simulated the same game.
+
+use std::{
+    io::Read,
+    net::{TcpListener, TcpStream},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+const FRAMES: i32 = 600;
+const TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Kills the process when the test ends, also when it fails
+struct KillOnDrop(Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("no free port");
+    listener.local_addr().unwrap().port()
+}
+
+fn start_signaling_server(port: u16) -> KillOnDrop {
+    let server = Command::new(env!("CARGO_BIN_EXE_signaling_server"))
+        .args(["--host", &format!("127.0.0.1:{port}")])
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("failed to start the signaling server");
+    let server = KillOnDrop(server);
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        assert!(Instant::now() < deadline, "signaling server didn't start");
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    server
+}
+
+fn start_game(port: u16) -> KillOnDrop {
+    let game = Command::new(env!("CARGO_BIN_EXE_tunneltanktournament"))
+        .args(["--headless", "--frames", &FRAMES.to_string()])
+        .args(["--match-url", &format!("ws://127.0.0.1:{port}")])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to start the game");
+    KillOnDrop(game)
+}
+
+/// Waits for the game to exit and returns the report it printed
+fn report(game: &mut KillOnDrop) -> String {
+    let deadline = Instant::now() + TIMEOUT;
+
+    let status = loop {
+        if let Some(status) = game.0.try_wait().unwrap() {
+            break status;
+        }
+        assert!(Instant::now() < deadline, "game didn't finish in time");
+        thread::sleep(Duration::from_millis(100));
+    };
+
+    let mut stdout = String::new();
+    game.0
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut stdout)
+        .unwrap();
+
+    assert!(status.success(), "game exited with {status}");
+
+    stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("REPORT "))
+        .unwrap_or_else(|| panic!("game didn't report its state:\n{stdout}"))
+        .to_string()
+}
+
+#[test]
+fn peers_simulate_the_same_match() {
+    let port = free_port();
+    let _server = start_signaling_server(port);
+
+    let mut first = start_game(port);
+    let mut second = start_game(port);
+
+    let first = report(&mut first);
+    let second = report(&mut second);
+
+    assert!(
+        first.starts_with(&format!("frame={FRAMES} ")),
+        "unexpected report: {first}"
+    );
+    assert!(
+        first.ends_with(" desyncs=0"),
+        "first peer desynced: {first}"
+    );
+    assert!(
+        second.ends_with(" desyncs=0"),
+        "second peer desynced: {second}"
+    );
+    assert_eq!(first, second, "peers ended up in different states");
+}